@@ -0,0 +1,92 @@
+//! Exact-count winner selection. The lots `1..=total_quantity` are shuffled by a keyed
+//! pseudo-random permutation, and the first `target_quantity` lots of the shuffled order
+//! win. Only the seed is stored, any lot is checked in O(1) by computing its position.
+//!
+//! The permutation is a Feistel network over the smallest even power of two domain
+//! holding every lot, positions out of range are walked through the network again
+//! (cycle walking) until they land in range, so it stays a bijection on `0..total_quantity`.
+//!
+//! Prize tiers take disjoint ranges of positions of one permutation, e.g. the first lot
+//! wins the jackpot and the next ten the second prizes, so a lot wins at most one tier.
+
+use crate::tails::{SplitMix64, TailRng};
+
+const FEISTEL_ROUNDS: usize = 4;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Permutation {
+    keys: [u64; FEISTEL_ROUNDS],
+    half_bits: u32,
+    total_quantity: u128,
+}
+
+impl Permutation {
+    pub fn new(seed: &[u8; 32], total_quantity: u128) -> Self {
+        assert!(total_quantity > 0, "total quantity is 0");
+        assert!(total_quantity <= 1u128 << 126, "total quantity is too large");
+        let mut rng = SplitMix64::from_seed(seed);
+        let mut keys = [0u64; FEISTEL_ROUNDS];
+        for key in keys.iter_mut() {
+            *key = rng.next_u64();
+        }
+
+        // 2^(2 * half_bits) >= total_quantity
+        let bits = 128 - (total_quantity - 1).leading_zeros();
+        let half_bits = ((bits + 1) / 2).max(1);
+        Permutation { keys, half_bits, total_quantity }
+    }
+
+    /// Position of `lot` in the shuffled order, starting from 0.
+    pub fn position_of(&self, lot: u128) -> u128 {
+        assert!(lot > 0 && lot <= self.total_quantity, "invalid lot number");
+        let mut position = self.encrypt(lot - 1);
+        while position >= self.total_quantity {
+            position = self.encrypt(position);
+        }
+        position
+    }
+
+    /// Whether `lot` is among the first `target_quantity` lots of the shuffled order.
+    pub fn is_selected(&self, lot: u128, target_quantity: u128) -> bool {
+        self.is_in_range(lot, 0, target_quantity)
+    }
+
+    /// Whether the position of `lot` is in `first..first + quantity`.
+    pub fn is_in_range(&self, lot: u128, first: u128, quantity: u128) -> bool {
+        if lot == 0 || lot > self.total_quantity {
+            return false
+        }
+        let position = self.position_of(lot);
+        position >= first && position - first < quantity
+    }
+
+    fn encrypt(&self, value: u128) -> u128 {
+        let mask = (1u128 << self.half_bits) - 1;
+        let (mut left, mut right) = (value >> self.half_bits, value & mask);
+        for key in &self.keys {
+            let next = left ^ (round(right as u64, *key) as u128 & mask);
+            left = right;
+            right = next;
+        }
+        (left << self.half_bits) | right
+    }
+}
+
+/// Count selected lots among `start..=end`.
+pub fn count_selected(permutation: &Permutation, start: u128, end: u128, target_quantity: u128) -> u128 {
+    count_in_range(permutation, start, end, 0, target_quantity)
+}
+
+/// Count lots among `start..=end` whose position is in `first..first + quantity`.
+pub fn count_in_range(permutation: &Permutation, start: u128, end: u128, first: u128, quantity: u128) -> u128 {
+    (start.max(1)..=end.min(permutation.total_quantity))
+        .filter(|lot| permutation.is_in_range(*lot, first, quantity))
+        .count() as u128
+}
+
+fn round(half: u64, key: u64) -> u64 {
+    let mut z = half ^ key;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
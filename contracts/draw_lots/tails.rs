@@ -0,0 +1,203 @@
+//! The winning tail algorithm of `DrawLots`, free of any contract state so it can be
+//! run and tested off-chain.
+//!
+//! Lots are numbered `1..=total_quantity`. A tail `t` with `pos` digits matches every
+//! lot `n` with `n % 10^pos == t`, so one tail stands for about `total_quantity / 10^pos`
+//! lots. The i-th decimal digit of the win rate tells how many tails of `i` digits to
+//! take, those tails share their lower `i - 1` digits and spread their i-th digit evenly
+//! over 0..9. Lots still missing in the end are added with tails of the highest `pos`,
+//! each of them matches exactly one lot.
+
+use ink_prelude::{
+    vec::Vec,
+    collections::BTreeMap,
+};
+
+/// Source of random numbers for the algorithm.
+pub trait TailRng {
+    fn next_u64(&mut self) -> u64;
+}
+
+/// SplitMix64, cheap and good enough to spread tails, all entropy comes from the seed.
+/// The whole draw can be replayed off-chain from the seed recorded by the contract.
+#[derive(Debug, Clone)]
+pub struct SplitMix64 {
+    state: u64,
+}
+
+impl SplitMix64 {
+    pub fn new(state: u64) -> Self {
+        SplitMix64 { state }
+    }
+
+    /// Fold a 32 bytes seed into the generator state.
+    pub fn from_seed(seed: &[u8; 32]) -> Self {
+        let mut state = 0u64;
+        for chunk in seed.chunks(8) {
+            let mut bytes = [0u8; 8];
+            bytes.copy_from_slice(chunk);
+            state ^= u64::from_le_bytes(bytes);
+        }
+        SplitMix64 { state }
+    }
+}
+
+impl TailRng for SplitMix64 {
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+}
+
+/// Result of a draw.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Draw {
+    /// Mapping from tail to pos (its number of digits).
+    pub winning_tails: BTreeMap<u128, u8>,
+    /// False means the tails describe the losing lots, it happens when the win rate is over 50%.
+    pub is_winning: bool,
+    /// Number of lots matched by the tails.
+    pub matched_quantity: u128,
+}
+
+/// Draw tails such that exactly `target_quantity` of the lots `1..=total_quantity` win.
+pub fn draw_tails<R: TailRng>(rng: &mut R, target_quantity: u128, total_quantity: u128) -> Draw {
+    assert!(
+        target_quantity > 0 && target_quantity < total_quantity,
+        "target quantity must be greater than 0 and less than total quantity"
+    );
+    let highest_pos = get_digits_length(total_quantity);
+
+    // if win rate is greater than 50%, change to calculate not winning tails
+    let (mut target, mut is_winning) = (target_quantity, true);
+    if target * 2 > total_quantity {
+        target = total_quantity - target;
+        is_winning = false;
+    }
+
+    let mut winning_tails: BTreeMap<u128, u8> = BTreeMap::new();
+    let mut matched = 0u128;
+
+    // every pos but the highest one, its tails are kept to replenish exactly.
+    for pos in 1..highest_pos {
+        let rate_bit = get_rate_bit(target, total_quantity, pos);
+        if rate_bit == 0 || matched == target { continue }
+
+        let base = get_free_base(rng, &winning_tails, pos - 1);
+        let step = 10u128.pow(pos as u32 - 1);
+        for k in get_spread_digits(rate_bit, rng.next_u64()) {
+            let tail = base + k as u128 * step;
+            if tail > total_quantity { continue }
+            let quantity = get_quantity_per_tail(pos, tail, total_quantity);
+            // revoke the tail if it goes over the target, the lack is replenished in the end.
+            if quantity == 0 || matched + quantity > target { continue }
+            matched += quantity;
+            winning_tails.insert(tail, pos);
+        }
+    }
+
+    // replenish lack tails in the end, one lot for one tail.
+    while matched < target {
+        let tail = (rng.next_u64() as u128) % total_quantity + 1;
+        if is_covered(&winning_tails, tail, highest_pos) { continue }
+        winning_tails.insert(tail, highest_pos);
+        matched += 1;
+    }
+
+    Draw { winning_tails, is_winning, matched_quantity: matched }
+}
+
+/// Count lots in `1..=total_quantity` matched by a tail of `pos` digits.
+pub fn get_quantity_per_tail(pos: u8, tail: u128, total_quantity: u128) -> u128 {
+    let mut quantity_per_tail = total_quantity / 10u128.pow(pos as u32);
+    let pos_value = total_quantity % 10u128.pow(pos as u32);
+
+    // if the winning tail is not zero and less than pos_value,
+    // quantity for this tail should plus one
+    if tail != 0 && tail <= pos_value {
+        quantity_per_tail += 1;
+    }
+    quantity_per_tail
+}
+
+pub fn get_digits_length(digit: u128) -> u8 {
+    let (mut _digit, mut _length) = (digit, 0);
+
+    loop {
+        if _digit == 0 { break }
+        _length += 1;
+        _digit /= 10;
+    }
+    assert!(_length > 0, "Invalid digit, the digit is 0");
+    _length
+}
+
+/// Whether `number`, read with `pos` digits, ends with any of the tails.
+pub fn is_covered(winning_tails: &BTreeMap<u128, u8>, number: u128, pos: u8) -> bool {
+    (1..=pos).any(|n| {
+        let tail = number % 10u128.pow(n as u32);
+        winning_tails.get(&tail) == Some(&n)
+    })
+}
+
+/// Count lots in `start..=end` ending with any of the tails, the tails must not overlap
+/// as in a `Draw`. A tail with `pos` digits matches one number out of every 10^pos, so each
+/// tail is counted by residues instead of walking through the section.
+pub fn count_winning_lots(start: u128, end: u128, winning_tails: &BTreeMap<u128, u8>) -> u128 {
+    if start > end { return 0 }
+    let mut winning_quantity = 0u128;
+    for (tail, pos) in winning_tails {
+        let modulus = 10u128.pow(*pos as u32);
+        winning_quantity += count_residues_to(end, *tail, modulus)
+            - count_residues_to(start.saturating_sub(1), *tail, modulus);
+    }
+    winning_quantity
+}
+
+/// Count numbers `n` in `1..=upper` with `n % modulus == tail`.
+fn count_residues_to(upper: u128, tail: u128, modulus: u128) -> u128 {
+    // 0 is never a lot number, skip it when the tail is 0.
+    let first = if tail == 0 { modulus } else { tail };
+    if upper < first { return 0 }
+    (upper - first) / modulus + 1
+}
+
+/// Brute-force reference: enumerate `1..=total_quantity` and collect the winning lots.
+pub fn get_winning_numbers(draw: &Draw, total_quantity: u128) -> Vec<u128> {
+    (1..=total_quantity)
+        .filter(|n| {
+            let matched = draw.winning_tails.iter()
+                .any(|(tail, pos)| n % 10u128.pow(*pos as u32) == *tail);
+            matched == draw.is_winning
+        })
+        .collect()
+}
+
+/// The `pos`-th decimal digit of `target / total`.
+fn get_rate_bit(target: u128, total: u128, pos: u8) -> u8 {
+    (target * 10u128.pow(pos as u32) / total % 10) as u8
+}
+
+/// Random suffix of `len` digits which doesn't end with any tail drawn before,
+/// every tail built on it is then free from overlaps.
+fn get_free_base<R: TailRng>(rng: &mut R, winning_tails: &BTreeMap<u128, u8>, len: u8) -> u128 {
+    if len == 0 { return 0 }
+    loop {
+        let base = (rng.next_u64() as u128) % 10u128.pow(len as u32);
+        if !is_covered(winning_tails, base, len) {
+            return base
+        }
+    }
+}
+
+/// `count` digits spread evenly over 0..9 from a random offset,
+/// eg: 5 => 02468, 4 => 0257, shifted by the offset.
+fn get_spread_digits(count: u8, rand: u64) -> Vec<u8> {
+    let offset = (rand % 10) as u8;
+    (0..count)
+        .map(|j| (j * 10 / count + offset) % 10)
+        .collect()
+}
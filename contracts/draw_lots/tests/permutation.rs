@@ -0,0 +1,70 @@
+use draw_lots::permutation::{count_in_range, count_selected, Permutation};
+use draw_lots::tails::{SplitMix64, TailRng};
+
+fn random_seed(rng: &mut SplitMix64) -> [u8; 32] {
+    let mut seed = [0u8; 32];
+    for chunk in seed.chunks_mut(8) {
+        chunk.copy_from_slice(&rng.next_u64().to_le_bytes());
+    }
+    seed
+}
+
+#[test]
+fn positions_are_a_permutation() {
+    let mut rng = SplitMix64::new(5);
+    for total_quantity in 1..=600u128 {
+        let permutation = Permutation::new(&random_seed(&mut rng), total_quantity);
+        let mut seen = vec![false; total_quantity as usize];
+        for lot in 1..=total_quantity {
+            let position = permutation.position_of(lot) as usize;
+            assert!(!seen[position], "position {} taken twice of total {}", position, total_quantity);
+            seen[position] = true;
+        }
+    }
+}
+
+#[test]
+fn exactly_target_lots_are_selected() {
+    let mut pairs = SplitMix64::new(11);
+    for _ in 0..1000 {
+        let total_quantity = (pairs.next_u64() % 5000) as u128 + 2;
+        let target_quantity = (pairs.next_u64() as u128) % (total_quantity - 1) + 1;
+        let permutation = Permutation::new(&random_seed(&mut pairs), total_quantity);
+        assert_eq!(count_selected(&permutation, 1, total_quantity, target_quantity), target_quantity);
+    }
+}
+
+#[test]
+fn same_seed_selects_the_same_lots() {
+    let seed = [3u8; 32];
+    let first = Permutation::new(&seed, 10_000);
+    let second = Permutation::new(&seed, 10_000);
+    for lot in 1..=10_000 {
+        assert_eq!(first.position_of(lot), second.position_of(lot));
+    }
+}
+
+#[test]
+fn tiers_take_disjoint_ranges() {
+    let mut rng = SplitMix64::new(17);
+    for _ in 0..200 {
+        let total_quantity = (rng.next_u64() % 2000) as u128 + 16;
+        let permutation = Permutation::new(&random_seed(&mut rng), total_quantity);
+        let mut tiers = Vec::new();
+        let mut first = 0u128;
+        for _ in 0..3 {
+            let quantity = (rng.next_u64() as u128) % ((total_quantity - first) / 4) + 1;
+            tiers.push((first, quantity));
+            first += quantity;
+        }
+        for (first, quantity) in &tiers {
+            assert_eq!(count_in_range(&permutation, 1, total_quantity, *first, *quantity), *quantity);
+        }
+        for lot in 1..=total_quantity {
+            let wins = tiers.iter()
+                .filter(|(first, quantity)| permutation.is_in_range(lot, *first, *quantity))
+                .count();
+            assert!(wins <= 1, "lot {} wins {} tiers of total {}", lot, wins, total_quantity);
+        }
+    }
+}
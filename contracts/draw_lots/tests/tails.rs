@@ -0,0 +1,117 @@
+use std::collections::BTreeMap;
+
+use draw_lots::tails::{
+    count_winning_lots,
+    draw_tails,
+    get_winning_numbers,
+    Draw,
+    SplitMix64,
+    TailRng,
+};
+
+fn assert_draw(draw: &Draw, target_quantity: u128, total_quantity: u128) {
+    let winning = get_winning_numbers(draw, total_quantity);
+    assert_eq!(
+        winning.len() as u128, target_quantity,
+        "target {} of total {}: {:?}", target_quantity, total_quantity, draw
+    );
+
+    for (tail, pos) in &draw.winning_tails {
+        assert!(*tail <= total_quantity, "tail {} exceeds total {}", tail, total_quantity);
+        // no tail is a suffix of another one.
+        for (other, other_pos) in &draw.winning_tails {
+            if other_pos > pos {
+                assert_ne!(other % 10u128.pow(*pos as u32), *tail, "{} overlaps {}", other, tail);
+            }
+        }
+    }
+}
+
+#[test]
+fn small_totals_are_exact_for_every_target() {
+    let mut rng = SplitMix64::new(7);
+    for total_quantity in 2..=300u128 {
+        for target_quantity in 1..total_quantity {
+            let draw = draw_tails(&mut rng, target_quantity, total_quantity);
+            assert_draw(&draw, target_quantity, total_quantity);
+        }
+    }
+}
+
+#[test]
+fn random_pairs_are_exact() {
+    let mut pairs = SplitMix64::new(42);
+    let mut rng = SplitMix64::new(2022);
+    for _ in 0..3000 {
+        let total_quantity = (pairs.next_u64() % 20_000) as u128 + 2;
+        let target_quantity = (pairs.next_u64() as u128) % (total_quantity - 1) + 1;
+        let draw = draw_tails(&mut rng, target_quantity, total_quantity);
+        assert_draw(&draw, target_quantity, total_quantity);
+    }
+}
+
+#[test]
+fn win_rate_over_half_draws_losing_tails() {
+    let mut rng = SplitMix64::new(1);
+    let draw = draw_tails(&mut rng, 900, 1000);
+    assert!(!draw.is_winning);
+    assert_eq!(draw.matched_quantity, 100);
+    assert_draw(&draw, 900, 1000);
+}
+
+#[test]
+fn same_seed_replays_the_same_draw() {
+    let seed = [9u8; 32];
+    let first = draw_tails(&mut SplitMix64::from_seed(&seed), 1234, 56789);
+    let second = draw_tails(&mut SplitMix64::from_seed(&seed), 1234, 56789);
+    assert_eq!(first, second);
+}
+
+fn count_by_loop(start: u128, end: u128, winning_tails: &BTreeMap<u128, u8>) -> u128 {
+    (start..=end)
+        .filter(|n| winning_tails.iter().any(|(tail, pos)| n % 10u128.pow(*pos as u32) == *tail))
+        .count() as u128
+}
+
+#[test]
+fn counted_winning_lots_match_the_loop() {
+    let fixed: Vec<BTreeMap<u128, u8>> = vec![
+        BTreeMap::from([(0, 1)]),
+        BTreeMap::from([(0, 2)]),
+        BTreeMap::from([(0, 3), (7, 1)]),
+        BTreeMap::from([(5, 2), (42, 2)]),
+        BTreeMap::from([(123, 3), (10, 2), (9, 1)]),
+    ];
+    for winning_tails in &fixed {
+        for start in 1..=250u128 {
+            for end in (start - 1)..=(start + 250) {
+                assert_eq!(
+                    count_winning_lots(start, end, winning_tails),
+                    count_by_loop(start, end, winning_tails),
+                    "{:?} in {}..={}", winning_tails, start, end
+                );
+            }
+        }
+    }
+
+    let mut rng = SplitMix64::new(13);
+    for _ in 0..300 {
+        let total_quantity = (rng.next_u64() % 3000) as u128 + 2;
+        let target_quantity = (rng.next_u64() as u128) % (total_quantity - 1) + 1;
+        let draw = draw_tails(&mut rng, target_quantity, total_quantity);
+        let start = (rng.next_u64() as u128) % total_quantity + 1;
+        let end = (rng.next_u64() as u128) % total_quantity + 1;
+        assert_eq!(
+            count_winning_lots(start, end, &draw.winning_tails),
+            count_by_loop(start, end, &draw.winning_tails),
+            "{:?} in {}..={}", draw, start, end
+        );
+    }
+}
+
+#[test]
+fn start_after_end_counts_nothing() {
+    let winning_tails = BTreeMap::from([(0, 1), (3, 1)]);
+    assert_eq!(count_winning_lots(20, 10, &winning_tails), 0);
+    assert_eq!(count_winning_lots(2, 1, &winning_tails), 0);
+}
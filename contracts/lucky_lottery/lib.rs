@@ -1,9 +1,16 @@
 #![cfg_attr(not(feature = "std"), no_std)]
 
+pub use self::lucky_lottery::LuckyLottery;
 
 #[brush::contract]
 mod lucky_lottery {
-    use draw_lots::DrawLots;
+    use draw_lots::{
+        DrawLots,
+        permutation::{count_in_range, Permutation},
+        tails::count_winning_lots,
+    };
+    use nft_factory::NftFactory;
+    use randomness_provider::commit_end_of;
     use ink_env::call::FromAccountId;
     use brush::modifiers;
     use ownable::traits::*;
@@ -18,15 +25,115 @@ mod lucky_lottery {
         vec::Vec,
         string::String,
         collections::BTreeMap,
-        collections::btree_map::Entry as BEntry,
     };
+    use ink_env::call::{
+        build_call,
+        utils::ReturnType,
+        ExecutionInput,
+        Selector,
+    };
+    use psp22::traits::PSP22Error;
+    use psp1155::traits::{Id, PSP1155Receiver, PSP1155ReceiverError, PSP1155AsDependency};
     use scale::{Encode, Decode};
+    use core::convert::TryFrom;
+
+    /// Selectors of the PSP22 messages the lottery calls on reward tokens.
+    const PSP22_BALANCE_OF_SELECTOR: [u8; 4] = [0x65, 0x68, 0x38, 0x2f];
+    const PSP22_TRANSFER_SELECTOR: [u8; 4] = [0xdb, 0x20, 0xf9, 0xf5];
+    const PSP22_TRANSFER_FROM_SELECTOR: [u8; 4] = [0x54, 0xb3, 0xc7, 0x6e];
+
+    /// The keeper of a scheduled draw gets at most 10% of the round reward.
+    const MAX_KEEPER_RATIO: u128 = 1000;
+
+    /// Exact tiers count a user's winners lot by lot, a pool with Exact tiers must cap the lots
+    /// of a user in a round so a claim stays within the gas limit.
+    const MAX_EXACT_LOTS_PER_USER: u128 = 1000;
 
     #[derive(Debug, PartialEq, Eq, Encode, Decode)]
     #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
     pub enum Error {
         Custom(String),
-        NotFound,
+        /// The caller is not the mystery box market.
+        NotMysteryBoxMarket,
+        /// The pool has never received lottery data.
+        PoolNotFound,
+        /// The lottery round doesn't exist in the pool.
+        RoundNotFound,
+        /// The lottery round hasn't been drawn yet.
+        RoundNotDrawn,
+        /// The user bought nothing in the round.
+        UserNotInRound,
+        /// The user has no purchase with this buy times.
+        BuyTimesOutOfRange,
+        /// The reward of the purchase has been received.
+        AlreadyClaimed,
+        /// Fewer lots than the pool's minimum were bought in the round.
+        BelowMinimumParticipants,
+        /// The winning quantity of every tier must be greater than 0, and the winners of all
+        /// tiers less than the total quantity.
+        InvalidWinQuantity,
+        /// A round needs at least one tier and the tier shares must add up to 10000,
+        /// rounds with several tiers draw them all in Exact mode.
+        InvalidTiers,
+        /// The tier doesn't exist in the round.
+        TierNotFound,
+        /// The token isn't registered as a reward token.
+        TokenNotRegistered,
+        InsufficientBalance,
+        TransferFailed,
+        /// The pool hasn't escrowed enough NFTs for the prizes of the tiers.
+        InsufficientNftPrize,
+        NftTransferFailed,
+        /// The pool has no draw schedule.
+        ScheduleNotFound,
+        /// The trigger of the pool's draw schedule isn't reached yet.
+        DrawNotDue,
+        /// The keeper ratio is over `MAX_KEEPER_RATIO`.
+        InvalidKeeperRatio,
+        /// The schedule's interval moves its trigger out of range.
+        InvalidSchedule,
+        /// The round has no deadline, or it isn't reached yet.
+        RoundNotExpired,
+        /// The round reached the minimum participants and will be drawn.
+        MinimumReached,
+        /// The round was cancelled, its lots are refunded instead of drawn.
+        RoundCancelled,
+        /// The round isn't cancelled, there is no refund.
+        RoundNotCancelled,
+        /// The refund of the user has been claimed.
+        AlreadyRefunded,
+        /// A pool with the id has been created.
+        PoolExists,
+        /// The pool is paused, it takes no purchases and no scheduled draws.
+        PoolPaused,
+        /// The pool is closed, it takes no purchases.
+        PoolClosed,
+        /// The config has an invalid reward ratio bound or tier setup.
+        InvalidPoolConfig,
+        /// The reward ratio is out of the pool's bounds.
+        RewardRatioOutOfBounds,
+        /// The user would hold more lots than the pool allows in a round.
+        MaxLotsExceeded,
+        /// The rounds of the pool are still in the legacy layout, see `migrate_pool`.
+        PoolNotMigrated,
+        /// The claim period of the round is over, its unclaimed rewards went back to the pool.
+        ClaimExpired,
+        /// The round can't be settled yet, its claim period isn't over.
+        ClaimNotExpired,
+        /// The lottery owes the user nothing in the round.
+        NothingOwed,
+        /// Exact tiers need `max_lots_per_user` between 1 and `MAX_EXACT_LOTS_PER_USER`,
+        /// and no user of the round may hold more lots than that.
+        ExactTierUncapped,
+        /// The round isn't opened with `open_round`, or its sales have ended.
+        RoundNotOpen,
+        /// The round is already bound to a commit–reveal round.
+        RoundAlreadyOpen,
+        /// The commit–reveal round doesn't exist, its commits are closed, or it is bound
+        /// to another round.
+        SeedRoundUnavailable,
+        /// The round still sells lots, it can be drawn after its `sales_end` block.
+        SalesNotEnded,
     }
 
     impl From<OwnableError> for Error {
@@ -38,6 +145,325 @@ mod lucky_lottery {
         }
     }
 
+    #[ink(event)]
+    pub struct PoolCreated {
+        #[ink(topic)]
+        pool_id: u128,
+        config: PoolConfig,
+    }
+
+    #[ink(event)]
+    pub struct PoolUpdated {
+        #[ink(topic)]
+        pool_id: u128,
+        config: PoolConfig,
+    }
+
+    #[ink(event)]
+    pub struct PoolClosed {
+        #[ink(topic)]
+        pool_id: u128,
+    }
+
+    #[ink(event)]
+    pub struct MysteryBoxMarketChanged {
+        #[ink(topic)]
+        market: AccountId,
+    }
+
+    #[ink(event)]
+    pub struct NftFactoryChanged {
+        #[ink(topic)]
+        nft_factory: AccountId,
+    }
+
+    #[ink(event)]
+    pub struct RewardTokenAdded {
+        #[ink(topic)]
+        pool_id: u128,
+        #[ink(topic)]
+        token20: AccountId,
+    }
+
+    #[ink(event)]
+    pub struct RewardDeposited {
+        #[ink(topic)]
+        pool_id: u128,
+        #[ink(topic)]
+        user: AccountId,
+        token20: AccountId,
+        amount: u128,
+    }
+
+    #[ink(event)]
+    pub struct NftPrizeDeposited {
+        #[ink(topic)]
+        pool_id: u128,
+        #[ink(topic)]
+        user: AccountId,
+        id: Id,
+        amount: Balance,
+    }
+
+    /// Lots `section.0..=section.1` of the round were bought by the user.
+    #[ink(event)]
+    pub struct LotsPurchased {
+        #[ink(topic)]
+        pool_id: u128,
+        #[ink(topic)]
+        lot_times: u128,
+        #[ink(topic)]
+        user: AccountId,
+        section: (u128, u128),
+        token20: AccountId,
+        amount: u128,
+    }
+
+    /// The round sells lots until `sales_end`, the commit window of `seed_round`, and is drawn
+    /// with its seed, its reward ratio and tiers.
+    #[ink(event)]
+    pub struct RoundOpened {
+        #[ink(topic)]
+        pool_id: u128,
+        #[ink(topic)]
+        lot_times: u128,
+        seed_round: u32,
+        sales_end: BlockNumber,
+        reward_ratio: u128,
+        tiers: Vec<TierSetting>,
+    }
+
+    #[ink(event)]
+    pub struct RoundDrawn {
+        #[ink(topic)]
+        pool_id: u128,
+        #[ink(topic)]
+        lot_times: u128,
+        total_quantity: u128,
+        reward_ratio: u128,
+        reward_amount: Vec<(AccountId, u128)>,
+        tiers: u32,
+    }
+
+    #[ink(event)]
+    pub struct DrawTriggered {
+        #[ink(topic)]
+        pool_id: u128,
+        #[ink(topic)]
+        lot_times: u128,
+        #[ink(topic)]
+        keeper: AccountId,
+        keeper_rewards: Vec<(AccountId, u128)>,
+    }
+
+    #[ink(event)]
+    pub struct DrawScheduleSet {
+        #[ink(topic)]
+        pool_id: u128,
+        schedule: DrawSchedule,
+    }
+
+    #[ink(event)]
+    pub struct DrawScheduleRemoved {
+        #[ink(topic)]
+        pool_id: u128,
+    }
+
+    #[ink(event)]
+    pub struct RoundCancelled {
+        #[ink(topic)]
+        pool_id: u128,
+        #[ink(topic)]
+        lot_times: u128,
+        total_quantity: u128,
+        refund_amount: Vec<(AccountId, u128)>,
+    }
+
+    #[ink(event)]
+    pub struct RefundClaimed {
+        #[ink(topic)]
+        pool_id: u128,
+        #[ink(topic)]
+        lot_times: u128,
+        #[ink(topic)]
+        user: AccountId,
+        refunds: Vec<(AccountId, u128)>,
+    }
+
+    /// What the round didn't pay out went back to the pool.
+    #[ink(event)]
+    pub struct RoundSettled {
+        #[ink(topic)]
+        pool_id: u128,
+        #[ink(topic)]
+        lot_times: u128,
+        returned: Vec<(AccountId, u128)>,
+        returned_nft: Vec<(Id, Balance)>,
+    }
+
+    #[ink(event)]
+    pub struct DustSwept {
+        #[ink(topic)]
+        pool_id: u128,
+        #[ink(topic)]
+        token20: AccountId,
+        amount: u128,
+    }
+
+    #[ink(event)]
+    pub struct PoolMigrated {
+        #[ink(topic)]
+        pool_id: u128,
+        rounds_migrated: u32,
+        rounds_left: u32,
+    }
+
+    /// Payments of a claim which failed, the user gets them with `claim_owed`.
+    #[ink(event)]
+    pub struct PaymentOwed {
+        #[ink(topic)]
+        pool_id: u128,
+        #[ink(topic)]
+        lot_times: u128,
+        #[ink(topic)]
+        user: AccountId,
+        tokens: Vec<(AccountId, u128)>,
+        nfts: Vec<(Id, Balance)>,
+    }
+
+    #[ink(event)]
+    pub struct OwedPaid {
+        #[ink(topic)]
+        pool_id: u128,
+        #[ink(topic)]
+        lot_times: u128,
+        #[ink(topic)]
+        user: AccountId,
+        tokens: Vec<(AccountId, u128)>,
+        nfts: Vec<(Id, Balance)>,
+    }
+
+    #[ink(event)]
+    pub struct RewardReceived {
+        #[ink(topic)]
+        pool_id: u128,
+        #[ink(topic)]
+        lot_times: u128,
+        #[ink(topic)]
+        user: AccountId,
+        section: (u128, u128),
+        winning_quantity: u128,
+        rewards: Vec<(AccountId, u128)>,
+        nft_rewards: Vec<(Id, Balance)>,
+    }
+
+    /// How the winners of a round are drawn.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Encode, Decode, SpreadLayout, PackedLayout)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    pub enum DrawMode {
+        /// Winning tails, lots ending with any tail win (or lose if the polarity is inverted).
+        Tails,
+        /// Exactly `winning_quantity` lots picked by a seeded permutation of all lots.
+        Exact,
+    }
+
+    /// Prize tier of a round to draw, e.g. 1 jackpot, 10 second prizes and 100 third prizes.
+    #[derive(Debug, Clone, PartialEq, Eq, Encode, Decode, SpreadLayout, PackedLayout)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    pub struct TierSetting {
+        pub winning_quantity: u128,
+        pub reward_share: u128,  // share of the round reward, /10000
+        pub draw_mode: DrawMode,
+        /// NFTs every winning lot of the tier gets, taken from the pool's escrow.
+        pub nft_prize: Vec<(Id, Balance)>,
+    }
+
+    /// A drawn prize tier. The Exact tiers of a round take consecutive position ranges of one
+    /// permutation, so a lot wins at most one tier.
+    #[derive(Debug, Clone, PartialEq, Eq, Encode, Decode, SpreadLayout, PackedLayout)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    pub struct Tier {
+        winning_quantity: u128, // number of winners.
+        reward_share: u128,  // share of the round reward, /10000
+        draw_mode: DrawMode,
+        winning_tails: BTreeMap<u128, u8>,  // all winning tails.
+        is_winning_tails: bool,  // false means the tails are the losing lots, when win rate is over 50%.
+        exact_seed: [u8; 32],  // permutation seed of DrawMode::Exact, shared by the tiers of the round
+        offset: u128,  // first position of the tier in the permutation
+        reward_amount: BTreeMap<AccountId, u128>,  // token addr to amount
+        nft_prize: Vec<(Id, Balance)>,  // nft id to amount of each winning lot
+    }
+
+    /// Settings of a lottery pool.
+    #[derive(Debug, Clone, PartialEq, Eq, Encode, Decode, SpreadLayout, PackedLayout)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    pub struct PoolConfig {
+        /// Minimum lots of a round to be drawn.
+        pub min_participants: u128,
+        /// Bounds of the reward ratio (/10000) a round takes from the pool.
+        pub min_reward_ratio: u128,
+        pub max_reward_ratio: u128,
+        /// Reward ratio of the rounds opened while the pool has no draw schedule.
+        pub reward_ratio: u128,
+        /// Tokens the pool takes as lot payments and rewards.
+        pub reward_tokens: Vec<AccountId>,
+        /// Maximum lots of a user in a round, 0 means no limit.
+        /// Pools with Exact tiers need a limit of at most `MAX_EXACT_LOTS_PER_USER`.
+        pub max_lots_per_user: u128,
+        /// Time a round has to reach `min_participants`, counted from its first purchase,
+        /// 0 means rounds never expire.
+        pub round_duration: Timestamp,
+        /// Tiers of the rounds opened while the pool has no draw schedule, or whose schedule
+        /// brings no tiers of its own.
+        pub tiers: Vec<TierSetting>,
+        pub paused: bool,
+        /// Time winners and refunded users have to claim after a round is drawn or cancelled,
+        /// what is left unclaimed then goes back to the pool. 0 means claims never expire.
+        pub claim_period: Timestamp,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq, Encode, Decode, SpreadLayout, PackedLayout)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    pub struct Pool {
+        config: PoolConfig,
+        closed: bool,  // closed pools take no purchases, their rounds can still be drawn and claimed
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Encode, Decode)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    pub enum RoundStatus {
+        Open,  // selling lots
+        Drawn,
+        Cancelled,  // missed the minimum participants by the deadline, lots are refunded
+    }
+
+    /// When a scheduled round can be drawn.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Encode, Decode, SpreadLayout, PackedLayout)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    pub enum DrawTrigger {
+        /// Once the block number reaches it.
+        Block(BlockNumber),
+        /// Once the block timestamp reaches it.
+        Timestamp(Timestamp),
+        /// Once the round has sold this many lots.
+        Quantity(u128),
+    }
+
+    /// Draw schedule of a pool, anyone can trigger the draw once it is due.
+    #[derive(Debug, Clone, PartialEq, Eq, Encode, Decode, SpreadLayout, PackedLayout)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    pub struct DrawSchedule {
+        pub trigger: DrawTrigger,
+        /// Blocks or milliseconds the trigger moves on after each draw, 0 means the schedule
+        /// is removed after its draw. Quantity triggers apply to every round while it isn't 0.
+        pub interval: u64,
+        /// Reward ratio (/10000) and tiers of the rounds opened while the schedule is set,
+        /// empty tiers mean the pool's tiers.
+        pub reward_ratio: u128,
+        pub tiers: Vec<TierSetting>,
+        pub keeper_ratio: u128,  // keeper's share of the round reward, /10000
+    }
+
     #[derive(Debug, PartialEq, Eq, Encode, Decode, SpreadLayout, PackedLayout)]
     #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
     pub struct UserInfo {
@@ -45,17 +471,56 @@ mod lucky_lottery {
         // (start index, end index) to is received reward.
         sections: BTreeMap<(u128, u128), bool>,
         reward_amount: BTreeMap<AccountId, u128>,
+        nft_reward: BTreeMap<Id, Balance>,
+        contributed: BTreeMap<AccountId, u128>,  // token addr to amount paid into the pool
+        refunded: bool,
+        owed: BTreeMap<AccountId, u128>,  // token addr to rewards or refunds whose transfer failed
+        owed_nft: BTreeMap<Id, Balance>,  // nft id to prizes whose transfer failed
     }
 
     #[derive(Debug, PartialEq, Eq, Encode, Decode, SpreadLayout, PackedLayout)]
     #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
     pub struct Lottery {
-        winning_quantity: u128, // number of winners.
         total_quantity: u128, // all lots
         reward_ratio: u128,  // suggest 10000
-        winning_tails: BTreeMap<u128, u8>,  // all winning tails.
+        tiers: Vec<Tier>,  // prize tiers, empty until drawn
         reward_amount: BTreeMap<AccountId, u128>,  // token addr to amount
-        user_infos: BTreeMap<AccountId, UserInfo>, // user addr to user's info
+        deadline: Timestamp,  // the round can be cancelled after it if below the minimum, 0 means none
+        cancelled: bool,
+        contributed: BTreeMap<AccountId, u128>,  // token addr to amount paid into the pool
+        refund_amount: BTreeMap<AccountId, u128>,  // token addr to amount set aside for refunds
+        claim_deadline: Timestamp,  // last time to claim, 0 means none
+        claimed_amount: BTreeMap<AccountId, u128>,  // token addr to rewards or refunds paid
+        claimed_nft: BTreeMap<Id, Balance>,  // nft id to prizes paid
+        claimed_lots: u128,  // winning lots or refunded lots paid
+        settled: bool,  // the unclaimed rest went back to the pool
+        seed_round: u32,  // commit–reveal round the round is drawn with, 0 until opened
+        sales_end: BlockNumber,  // last block to buy lots, the commit end of `seed_round`
+        tier_settings: Vec<TierSetting>,  // tiers to draw, fixed when the round opens
+    }
+
+    /// A user of a round in the legacy layout, field for field the `UserInfo` rounds were
+    /// stored with before the migration. Only read by `migrate_pool`, don't change it.
+    #[derive(Debug, PartialEq, Eq, Encode, Decode, SpreadLayout, PackedLayout)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    pub struct LegacyUserInfo {
+        buy_quantity: u128,
+        sections: BTreeMap<(u128, u128), bool>,
+        reward_amount: BTreeMap<AccountId, u128>,
+    }
+
+    /// Round layout before rounds and users got their own storage cells, every round of a
+    /// pool was packed into one cell with all its users. Field for field the `Lottery` rounds
+    /// were stored as, only read by `migrate_pool`, don't change it.
+    #[derive(Debug, PartialEq, Eq, Encode, Decode, SpreadLayout, PackedLayout)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    pub struct LegacyLottery {
+        winning_quantity: u128,
+        total_quantity: u128,
+        reward_ratio: u128,
+        winning_tails: BTreeMap<u128, u8>,
+        reward_amount: BTreeMap<AccountId, u128>,
+        user_infos: BTreeMap<AccountId, LegacyUserInfo>,
     }
 
     #[ink(storage)]
@@ -63,18 +528,54 @@ mod lucky_lottery {
     pub struct LuckyLottery {
         #[OwnableStorageField]
         ownable: OwnableData,
-        min_lottery_addr_quantities: StorageHashMap<u128, u128>,
+        /// Mapping from pool_id to the pool settings.
+        pools: StorageHashMap<u128, Pool>,
         /// Mapping from (pool_id, token addr) to amount
         remain_amounts: StorageHashMap<u128, BTreeMap<AccountId, u128>>,
-        // Mapping from pool_id to Map(lottery times to Lottery)
-        lotteries: StorageHashMap<u128, BTreeMap<u128, Lottery>>,
+        // Legacy layout, mapping from pool_id to Map(lottery times to Lottery),
+        // emptied by `migrate_pool`.
+        lotteries: StorageHashMap<u128, BTreeMap<u128, LegacyLottery>>,
         mystery_box_market: AccountId,
-        token20s: StorageHashMap<AccountId, bool>,
         draw_lots: Lazy<DrawLots>,
+        /// nft factory contract, its items can be escrowed as prizes.
+        nft: Lazy<NftFactory>,
+        nft_factory: AccountId,
+        /// Mapping from pool_id to escrowed nft prizes not yet assigned to a round, id to amount.
+        nft_prizes: StorageHashMap<u128, BTreeMap<Id, Balance>>,
+        /// Mapping from pool_id to its draw schedule.
+        schedules: StorageHashMap<u128, DrawSchedule>,
+        /// Mapping from pool_id to its current lottery times, the round selling lots.
+        current_rounds: StorageHashMap<u128, u128>,
+        /// Mapping from (pool_id, lottery times) to the round.
+        rounds: StorageHashMap<(u128, u128), Lottery>,
+        /// Mapping from (pool_id, lottery times, user) to the user's info in the round.
+        user_infos: StorageHashMap<(u128, u128, AccountId), UserInfo>,
+        /// Mapping from token addr to the amount the lottery owes, in pools, rounds and refunds.
+        /// Anything the lottery holds above it is dust for `sweep_dust`.
+        accounted: StorageHashMap<AccountId, u128>,
+        /// Mapping from (pool_id, lottery times) to the most lots a user bought in the round.
+        max_user_lots: StorageHashMap<(u128, u128), u128>,
+        /// Mapping from pool_id to whether `migrate_pool` has counted the pool's amounts in `accounted`.
+        pool_accounted: StorageHashMap<u128, bool>,
+        /// Mapping from commit–reveal round id to the (pool_id, lottery times) drawn with its seed.
+        seed_rounds: StorageHashMap<u32, (u128, u128)>,
     }
 
     impl Ownable for LuckyLottery {}
 
+    impl PSP1155Receiver for LuckyLottery {
+        #[ink(message)]
+        fn before_received(
+            &mut self,
+            _operator: AccountId,
+            _from: AccountId,
+            _ids_to_amounts: Vec<(Id, Balance)>,
+            _data: Vec<u8>,
+        ) -> Result<(), PSP1155ReceiverError> {
+            Ok(())
+        }
+    }
+
     impl LuckyLottery {
         #[ink(constructor)]
         pub fn new(draw_lots: AccountId) -> Self {
@@ -83,9 +584,6 @@ mod lucky_lottery {
             let caller = Self::env().caller();
             instance._init_with_owner(caller);
             instance.mystery_box_market = caller;
-            let mut min_requires = StorageHashMap::new();
-            min_requires.insert(0, 500000);
-            instance.min_lottery_addr_quantities = min_requires;
 
             let draw_lots = FromAccountId::from_account_id(draw_lots);
             instance.draw_lots = Lazy::new(draw_lots);
@@ -94,274 +592,1579 @@ mod lucky_lottery {
 
         #[ink(message)]
         #[modifiers(only_owner)]
-        pub fn adjust_min_lottery_addr_quantity_of_pool(
-            &mut self, 
-            pool_id: u128, 
-            quantity: u128
-        ) -> Result<(), OwnableError> {
-            self.min_lottery_addr_quantities.insert(pool_id, quantity);
+        pub fn create_pool(&mut self, pool_id: u128, config: PoolConfig) -> Result<(), Error> {
+            validate_pool_config(&config)?;
+            match self.pools.entry(pool_id) {
+                Entry::Vacant(vacant) => { vacant.insert(Pool { config: config.clone(), closed: false }); },
+                Entry::Occupied(_) => return Err(Error::PoolExists),
+            }
+            self.env().emit_event(PoolCreated { pool_id, config });
             Ok(())
         }
 
+        /// Replace the config of a pool, the current round keeps its deadline.
         #[ink(message)]
-        pub fn add_reward_token(&mut self, token20: AccountId) {
-            self.only_mb_market();
-            match self.token20s.entry(token20) {
-                Entry::Vacant(vacant) => { vacant.insert(true); },
-                Entry::Occupied(_) => (),
+        #[modifiers(only_owner)]
+        pub fn update_pool(&mut self, pool_id: u128, config: PoolConfig) -> Result<(), Error> {
+            validate_pool_config(&config)?;
+            let pool = self.pools.get_mut(&pool_id).ok_or(Error::PoolNotFound)?;
+            if pool.closed {
+                return Err(Error::PoolClosed)
             }
+            pool.config = config.clone();
+            self.env().emit_event(PoolUpdated { pool_id, config });
+            Ok(())
         }
 
         #[ink(message)]
-        pub fn add_lottery_data(
-            &mut self,
-            pool_id: u128,
-            user: AccountId,
-            quantity: u128,
-            token20: AccountId,
-            amount: u128,
-        ) -> Result<(), Error> {
-            self.only_mb_market();
-            match self.lotteries.entry(pool_id) {
-                Entry::Vacant(vacant) => {
-                    let mut lot_pool: BTreeMap<u128, Lottery> = BTreeMap::new();
-                    let mut lot = Lottery::new();
-                    // init lot
-                    lot.total_quantity = quantity;
-                    let mut user_info = UserInfo::new();
-                    modify_user_info(&mut user_info, lot.total_quantity, quantity);
-                    lot.user_infos.insert(user, user_info);
-                    // init lot_pool, lot_times starts from 1
-                    lot_pool.insert(1, lot);
-                    vacant.insert(lot_pool);
-                }, 
-                Entry::Occupied(mut occupied) => {
-                    let lot_pool = occupied.get_mut();
-                    let lot_times = lot_pool.len() as u128;
-                    match lot_pool.entry(lot_times) {
-                        BEntry::Vacant(_) => return Err(Error::NotFound),
-                        BEntry::Occupied(mut boccupied) => {
-                            let lot = boccupied.get_mut();
-                            lot.total_quantity += quantity;
-                            match lot.user_infos.entry(user) {
-                                BEntry::Vacant(vacant) => {
-                                    let mut user_info = UserInfo::new();
-                                    modify_user_info(&mut user_info, lot.total_quantity, quantity);
-                                    vacant.insert(user_info);
-                                },
-                                BEntry::Occupied(mut uoccupied) => {
-                                    let user_info = uoccupied.get_mut();
-                                    modify_user_info(user_info, lot.total_quantity, quantity);
-                                },
-                            };
-                        },
-                    };
-                },
-            };
-            self.remain_amounts.entry(pool_id).and_modify(|btmap| {
-                btmap.entry(token20).and_modify(|v| *v += amount).or_insert(amount);
-            }).or_insert(BTreeMap::from([(token20, amount)]));
+        #[modifiers(only_owner)]
+        pub fn set_pool_paused(&mut self, pool_id: u128, paused: bool) -> Result<(), Error> {
+            let pool = self.pools.get_mut(&pool_id).ok_or(Error::PoolNotFound)?;
+            pool.config.paused = paused;
+            let config = pool.config.clone();
+            self.env().emit_event(PoolUpdated { pool_id, config });
             Ok(())
         }
 
+        /// Close a pool for good, it takes no more purchases and its schedule is removed.
+        /// The current round can still be drawn or cancelled, and all rounds claimed.
         #[ink(message)]
         #[modifiers(only_owner)]
-        pub fn draw_lottery(
-            &mut self,
-            pool_id: u128,
-            reward_ratio: u128,
-            salt: u32,
-            win_quantity: u128,
-        ) -> Result<(), Error> {
-            let lot_times = self.lot_times(pool_id);
-            let total_quantity = self.total_quantity(pool_id, lot_times);
-            assert!(total_quantity >= self.min_lottery_addr_quantity(pool_id),
-                "the amount of lottery addresses is too small");
-
-            let (winning_tails, _) = self.draw_lots.draw_lots(salt, win_quantity, total_quantity);
-            let reward_amount = match self.remain_amounts.entry(pool_id) {
-                Entry::Vacant(_) => return Err(Error::NotFound),
-                Entry::Occupied(mut occupied) => {
-                    let mut reward_amount = BTreeMap::new();
-                    let btmap = occupied.get_mut();
-                    for (k, v) in btmap {
-                        let reward = (*v).saturating_mul(reward_ratio).saturating_div(10000);
-                        *v -= reward;  // TODO: if *v == 0, should it remove this (K, V)?
-                        reward_amount.insert(*k, reward);
-                    } reward_amount
-                },
-            };
-            self.lotteries.entry(pool_id).and_modify(move |btmap| {
-                btmap.entry(lot_times).and_modify(move |lot| {
-                    lot.winning_quantity = win_quantity;
-                    lot.reward_ratio = reward_ratio;
-                    lot.winning_tails = winning_tails;
-                    lot.reward_amount = reward_amount;
-                });
-                // lot_times++
-                btmap.insert(lot_times + 1, Lottery::new());
-            });
-
+        pub fn close_pool(&mut self, pool_id: u128) -> Result<(), Error> {
+            let pool = self.pools.get_mut(&pool_id).ok_or(Error::PoolNotFound)?;
+            if pool.closed {
+                return Err(Error::PoolClosed)
+            }
+            pool.closed = true;
+            self.schedules.take(&pool_id);
+            self.env().emit_event(PoolClosed { pool_id });
             Ok(())
         }
 
+        /// Move up to `max_rounds` rounds of a pool from the legacy layout into per-round and
+        /// per-user cells, oldest first, returns the rounds left. The pool takes no purchases
+        /// and no draws until all of its rounds are moved.
         #[ink(message)]
-        pub fn receive_reward(&mut self, pool_id: u128, lot_times: u128, buy_times: u128) {
-            let caller = self.env().caller();
-            assert!(lot_times > 0 && lot_times < self.lot_times(pool_id), "invalid lottery times");
-            assert!(buy_times > 0 && buy_times < self.buy_times(pool_id, lot_times, caller), "invalid buy times");
-            let (start, end, is_received) = self.section_info(pool_id, lot_times, caller, buy_times);
-            assert!(!is_received, "already received");  // is_received is false means available
-
-            let (winning_tails, reward_amount, total_win_quantity) = self.lottery_info(pool_id, lot_times);
-
-            // TODO: optimize this loop
-            let mut winning_quantity = 0u128;
-            for num in start..end + 1 {
-                for (tail, pos) in &winning_tails {
-                    if num % 10u128.pow(*pos as u32) == *tail {
-                        winning_quantity += 1;
-                    }
+        #[modifiers(only_owner)]
+        pub fn migrate_pool(&mut self, pool_id: u128, max_rounds: u32) -> Result<u32, Error> {
+            let mut legacy = self.lotteries.take(&pool_id).ok_or(Error::PoolNotFound)?;
+            // the legacy layout kept no token accounting, count the pool in with its first batch.
+            if !self.pool_accounted.get(&pool_id).copied().unwrap_or(false) {
+                self.pool_accounted.insert(pool_id, true);
+                let remain = self.remain_amounts.get(&pool_id).cloned().unwrap_or_default();
+                for (token20, amount) in remain {
+                    self.account_in(token20, amount);
+                }
+            }
+            let (mut moved, mut last_round) = (0u32, 0u128);
+            while moved < max_rounds {
+                let lot_times = match legacy.keys().next() {
+                    Some(lot_times) => *lot_times,
+                    None => break,
+                };
+                let legacy_lot = legacy.remove(&lot_times).expect("the round exists");
+                // every legacy round but the last one, which is selling lots, is drawn.
+                let (lot, users) = legacy_lot.split(!legacy.is_empty());
+                // the legacy layout transferred nothing, the whole reward is still held.
+                for (token20, amount) in &lot.reward_amount {
+                    self.account_in(*token20, *amount);
+                }
+                for (user, user_info) in users {
+                    self.user_infos.insert((pool_id, lot_times, user), user_info);
                 }
+                self.rounds.insert((pool_id, lot_times), lot);
+                last_round = lot_times;
+                moved += 1;
             }
 
-            // TODO: reward transfering not implemented.
-            let user_infos = self.lotteries
-                .get_mut(&pool_id).unwrap()
-                .get_mut(&lot_times).unwrap().user_infos
-                .get_mut(&caller).unwrap();
-            for (token20, amount) in &reward_amount {
-                let user_reward = amount.saturating_mul(winning_quantity).saturating_div(total_win_quantity);
-                // let token20_instance = FromAccountId::from_account_id(*token20);
-                // token20_instance.transfer(caller, user_reward);
-                user_infos.reward_amount.entry(*token20)
-                    .and_modify(|v| *v += user_reward)
-                    .or_insert(user_reward);
+            let rounds_left = legacy.len() as u32;
+            if rounds_left == 0 {
+                // the last legacy round is the one selling lots.
+                self.current_rounds.insert(pool_id, last_round);
+            } else {
+                self.lotteries.insert(pool_id, legacy);
             }
-            user_infos.sections.entry((start, end))
-                .and_modify(|v| *v = true);
+            self.env().emit_event(PoolMigrated {
+                pool_id,
+                rounds_migrated: moved,
+                rounds_left,
+            });
+            Ok(rounds_left)
         }
 
-        fn lottery_info(
-            &self, 
-            pool_id: u128, 
-            lot_times: u128
-        ) -> (BTreeMap<u128, u8>, BTreeMap<AccountId, u128>, u128) {
-            let lot = self.lotteries
-                .get(&pool_id).unwrap()
-                .get(&lot_times).unwrap();
-            (lot.winning_tails.clone(), lot.reward_amount.clone(), lot.winning_quantity)
+        #[ink(message)]
+        #[modifiers(only_owner)]
+        pub fn set_mystery_box_market(&mut self, market: AccountId) -> Result<(), Error> {
+            self.mystery_box_market = market;
+            self.env().emit_event(MysteryBoxMarketChanged { market });
+            Ok(())
         }
 
-        fn only_mb_market(&self) {
-            assert_eq!(self.mystery_box_market, self.env().caller(), 
-                "only mystery box market contract authorized");
+        #[ink(message)]
+        pub fn mystery_box_market(&self) -> AccountId {
+            self.mystery_box_market
         }
 
-        fn lot_times(&self, pool_id: u128) -> u128 {
-            self.lotteries.get(&pool_id).unwrap().len() as u128
+        #[ink(message)]
+        #[modifiers(only_owner)]
+        pub fn set_nft_factory(&mut self, nft_factory: AccountId) -> Result<(), Error> {
+            let nft: NftFactory = FromAccountId::from_account_id(nft_factory);
+            self.nft = Lazy::new(nft);
+            self.nft_factory = nft_factory;
+            self.env().emit_event(NftFactoryChanged { nft_factory });
+            Ok(())
         }
 
-        fn total_quantity(&self, pool_id: u128, lot_times: u128) -> u128 {
-            let _total = self.lotteries
-                .get(&pool_id).unwrap()
-                .get(&lot_times).unwrap().total_quantity;
-            _total
+        #[ink(message)]
+        pub fn nft_factory(&self) -> AccountId {
+            self.nft_factory
         }
 
-        /// How many times does a account buy of current lottery times in current pool.
+        /// Escrow NftFactory items as prizes of the pool, the lottery must be approved
+        /// as an operator of the caller beforehand.
         #[ink(message)]
-        pub fn buy_times(&self, pool_id: u128, lot_times: u128, user: AccountId) -> u128 {
-            let _total = self.lotteries
-                .get(&pool_id).unwrap()
-                .get(&lot_times).unwrap().user_infos
-                .get(&user).unwrap()
-                .sections.len() as u128;
-            _total
+        pub fn deposit_nft_prize(&mut self, pool_id: u128, id: Id, amount: Balance) -> Result<(), Error> {
+            let caller = self.env().caller();
+            let lottery = self.env().account_id();
+            self.nft
+                .transfer_from(caller, lottery, id, amount, Vec::new())
+                .map_err(|_| Error::NftTransferFailed)?;
+            self.nft_prizes.entry(pool_id).and_modify(|btmap| {
+                btmap.entry(id).and_modify(|v| *v += amount).or_insert(amount);
+            }).or_insert(BTreeMap::from([(id, amount)]));
+            self.env().emit_event(NftPrizeDeposited {
+                pool_id,
+                user: caller,
+                id,
+                amount,
+            });
+            Ok(())
         }
 
-        fn section_info(
-            &self, 
-            pool_id: u128, 
-            lot_times: u128, 
-            user: AccountId, 
-            buy_times: u128
-        ) -> (u128, u128, bool) {
-            let _info = self.lotteries
-                .get(&pool_id).unwrap()
-                .get(&lot_times).unwrap().user_infos
-                .get(&user).unwrap().sections
-                .iter().collect::<Vec<_>>()[buy_times as usize - 1];
-            (_info.0.0, _info.0.1, *_info.1)
+        /// The market adds the payment token of its pool to the pool's reward tokens.
+        #[ink(message)]
+        pub fn add_reward_token(&mut self, pool_id: u128, token20: AccountId) -> Result<(), Error> {
+            self.only_mb_market()?;
+            let pool = self.pools.get_mut(&pool_id).ok_or(Error::PoolNotFound)?;
+            if !pool.config.reward_tokens.contains(&token20) {
+                pool.config.reward_tokens.push(token20);
+                self.env().emit_event(RewardTokenAdded { pool_id, token20 });
+            }
+            Ok(())
         }
 
+        /// Check that `user` can buy `quantity` lots of the pool's current round with `token20`,
+        /// the market calls it before taking any payment.
         #[ink(message)]
-        pub fn get_reward_token20s(&self) {}
+        pub fn check_lottery_data(
+            &self,
+            pool_id: u128,
+            user: AccountId,
+            quantity: u128,
+            token20: AccountId,
+        ) -> Result<(), Error> {
+            let pool = self.pools.get(&pool_id).ok_or(Error::PoolNotFound)?;
+            if pool.closed {
+                return Err(Error::PoolClosed)
+            }
+            if pool.config.paused {
+                return Err(Error::PoolPaused)
+            }
+            if !pool.config.reward_tokens.contains(&token20) {
+                return Err(Error::TokenNotRegistered)
+            }
+            if !self.current_rounds.contains_key(&pool_id) && self.lotteries.contains_key(&pool_id) {
+                return Err(Error::PoolNotMigrated)
+            }
+            let now = self.env().block_number();
+            let is_selling = self.lot_times(pool_id)
+                .and_then(|lot_times| self.lottery(pool_id, lot_times))
+                .map_or(false, |lot| lot.is_selling(now));
+            if !is_selling {
+                return Err(Error::RoundNotOpen)
+            }
+            if pool.config.max_lots_per_user > 0 {
+                let bought = self.lot_times(pool_id)
+                    .and_then(|lot_times| self.user_info(pool_id, lot_times, user))
+                    .map_or(0, |user_info| user_info.buy_quantity);
+                if bought + quantity > pool.config.max_lots_per_user {
+                    return Err(Error::MaxLotsExceeded)
+                }
+            }
+            Ok(())
+        }
 
         #[ink(message)]
-        pub fn get_winning_data(&self, _pool_id: u128, _lot_times: u128) {}
+        pub fn add_lottery_data(
+            &mut self,
+            pool_id: u128,
+            user: AccountId,
+            quantity: u128,
+            token20: AccountId,
+            amount: u128,
+        ) -> Result<(), Error> {
+            self.only_mb_market()?;
+            self.check_lottery_data(pool_id, user, quantity, token20)?;
+            let deadline = match self.pool_config(pool_id)?.round_duration {
+                0 => 0,
+                duration => self.env().block_timestamp() + duration,
+            };
+            let lot_times = self.lot_times(pool_id)?;
+            let lot = self.rounds.get_mut(&(pool_id, lot_times)).ok_or(Error::RoundNotFound)?;
+            lot.total_quantity += quantity;
+            // the deadline counts from the first purchase of the round.
+            if lot.deadline == 0 {
+                lot.deadline = deadline;
+            }
+            lot.contributed.entry(token20).and_modify(|v| *v += amount).or_insert(amount);
+            let end = lot.total_quantity;
+            let user_info = self.user_infos
+                .entry((pool_id, lot_times, user))
+                .or_insert_with(UserInfo::new);
+            modify_user_info(user_info, end, quantity, token20, amount);
+            let bought = user_info.buy_quantity;
+            let max_lots = self.max_user_lots.entry((pool_id, lot_times)).or_insert(0);
+            *max_lots = (*max_lots).max(bought);
+
+            self.remain_amounts.entry(pool_id).and_modify(|btmap| {
+                btmap.entry(token20).and_modify(|v| *v += amount).or_insert(amount);
+            }).or_insert(BTreeMap::from([(token20, amount)]));
+            // the market has transferred the amount to the lottery.
+            self.account_in(token20, amount);
+
+            self.env().emit_event(LotsPurchased {
+                pool_id,
+                lot_times,
+                user,
+                section: (end - quantity + 1, end),
+                token20,
+                amount,
+            });
+            Ok(())
+        }
 
+        /// Open the current round of a pool for sales and bind its draw to commit–reveal round
+        /// `seed_round` of the randomness provider. Lots are sold until the round's commit window
+        /// closes, so no secret is revealed while they are. The reward ratio and tiers are taken
+        /// from the draw schedule, or the pool config without one, and can't change afterwards.
         #[ink(message)]
-        pub fn get_lottery_reward_ratio(&self, _pool_id: u128, _lot_times: u128) {}
+        #[modifiers(only_owner)]
+        pub fn open_round(&mut self, pool_id: u128, seed_round: u32) -> Result<(), Error> {
+            let config = self.pool_config(pool_id)?;
+            if self.pools.get(&pool_id).map_or(false, |pool| pool.closed) {
+                return Err(Error::PoolClosed)
+            }
+            let lot_times = match self.lot_times(pool_id) {
+                Ok(lot_times) => lot_times,
+                Err(Error::PoolNotFound) => {
+                    // lot_times starts from 1
+                    self.current_rounds.insert(pool_id, 1);
+                    self.rounds.insert((pool_id, 1), Lottery::new());
+                    1
+                },
+                Err(e) => return Err(e),
+            };
+            if self.lottery(pool_id, lot_times)?.seed_round != 0 {
+                return Err(Error::RoundAlreadyOpen)
+            }
+            let provider = self.draw_lots.randomness_provider();
+            let sales_end = match commit_end_of(provider, seed_round) {
+                Some(commit_end) if seed_round != 0 && commit_end > self.env().block_number() => commit_end,
+                _ => return Err(Error::SeedRoundUnavailable),
+            };
+            if self.seed_rounds.contains_key(&seed_round) {
+                return Err(Error::SeedRoundUnavailable)
+            }
+            let (reward_ratio, tiers) = match self.schedules.get(&pool_id) {
+                Some(schedule) => (schedule.reward_ratio, schedule.tiers.clone()),
+                None => (config.reward_ratio, Vec::new()),
+            };
+            let tiers = if tiers.is_empty() { config.tiers.clone() } else { tiers };
+            check_draw_settings(&config, reward_ratio, &tiers)?;
+
+            self.seed_rounds.insert(seed_round, (pool_id, lot_times));
+            let lot = self.lottery_mut(pool_id, lot_times)?;
+            lot.seed_round = seed_round;
+            lot.sales_end = sales_end;
+            lot.reward_ratio = reward_ratio;
+            lot.tier_settings = tiers.clone();
+            self.env().emit_event(RoundOpened {
+                pool_id,
+                lot_times,
+                seed_round,
+                sales_end,
+                reward_ratio,
+                tiers,
+            });
+            Ok(())
+        }
 
+        /// Draw the current round once its sales have ended and its commit–reveal round is
+        /// finalized, the reward ratio (/10000) of the pool goes to the round and is split
+        /// between the tiers by their shares.
         #[ink(message)]
-        pub fn get_lottery_reward_amount(&self, _pool_id: u128, _lot_times: u128, _token20: AccountId) {}
-        
+        #[modifiers(only_owner)]
+        pub fn draw_lottery(&mut self, pool_id: u128) -> Result<(), Error> {
+            self.draw_round(pool_id, 0)?;
+            Ok(())
+        }
+
+        /// Set the draw schedule of a pool, replacing the old one.
         #[ink(message)]
-        pub fn get_user_buy_times(&self, _pool_id: u128, _lot_times: u128, _user: AccountId) {}
+        #[modifiers(only_owner)]
+        pub fn set_draw_schedule(&mut self, pool_id: u128, schedule: DrawSchedule) -> Result<(), Error> {
+            if schedule.keeper_ratio > MAX_KEEPER_RATIO {
+                return Err(Error::InvalidKeeperRatio)
+            }
+            if schedule.interval > 0 && schedule.next_trigger().is_none() {
+                return Err(Error::InvalidSchedule)
+            }
+            let pool = self.pools.get(&pool_id).ok_or(Error::PoolNotFound)?;
+            if pool.closed {
+                return Err(Error::PoolClosed)
+            }
+            let tiers = if schedule.tiers.is_empty() { &pool.config.tiers } else { &schedule.tiers };
+            check_draw_settings(&pool.config, schedule.reward_ratio, tiers)?;
+            self.schedules.insert(pool_id, schedule.clone());
+            self.env().emit_event(DrawScheduleSet { pool_id, schedule });
+            Ok(())
+        }
 
         #[ink(message)]
-        pub fn get_user_reward_amount(
-            &self,
-            _pool_id: u128,
-            _lot_times: u128,
-            _user: AccountId,
-            _token20: AccountId,
-        ) {}
+        #[modifiers(only_owner)]
+        pub fn remove_draw_schedule(&mut self, pool_id: u128) -> Result<(), Error> {
+            self.schedules.take(&pool_id).ok_or(Error::ScheduleNotFound)?;
+            self.env().emit_event(DrawScheduleRemoved { pool_id });
+            Ok(())
+        }
 
         #[ink(message)]
-        pub fn get_user_numbers(
-            &self,
-            _pool_id: u128,
-            _lot_times: u128,
-            _user: AccountId,
-            _buy_times: u128,
-        ) {}
+        pub fn get_draw_schedule(&self, pool_id: u128) -> Option<DrawSchedule> {
+            self.schedules.get(&pool_id).cloned()
+        }
 
+        /// Whether the pool's scheduled draw is due, the round still needs to pass
+        /// the draw checks, e.g. the minimum participants.
         #[ink(message)]
-        pub fn min_lottery_addr_quantity(&self, pool_id: u128) -> u128 {
-            *self.min_lottery_addr_quantities.get(&pool_id).unwrap_or(&500000)
+        pub fn is_draw_due(&self, pool_id: u128) -> bool {
+            let schedule = match self.schedules.get(&pool_id) {
+                Some(schedule) => schedule,
+                None => return false,
+            };
+            match schedule.trigger {
+                DrawTrigger::Block(block) => self.env().block_number() >= block,
+                DrawTrigger::Timestamp(timestamp) => self.env().block_timestamp() >= timestamp,
+                DrawTrigger::Quantity(quantity) => {
+                    self.lot_times(pool_id)
+                        .and_then(|lot_times| self.total_quantity(pool_id, lot_times))
+                        .map_or(false, |total| total >= quantity)
+                },
+            }
         }
 
+        /// Draw the current round of a pool by its schedule once it is due. Anyone can call
+        /// it, the caller gets the schedule's keeper share of the round reward.
         #[ink(message)]
-        pub fn get_token20_balance(&self, _token20: AccountId) {}
-    }
+        pub fn trigger_draw(&mut self, pool_id: u128) -> Result<(), Error> {
+            let schedule = self.schedules.get(&pool_id).cloned().ok_or(Error::ScheduleNotFound)?;
+            if self.pool_config(pool_id)?.paused {
+                return Err(Error::PoolPaused)
+            }
+            if !self.is_draw_due(pool_id) {
+                return Err(Error::DrawNotDue)
+            }
+            let next_trigger = match schedule.interval {
+                0 => None,
+                _ => Some(schedule.next_trigger().ok_or(Error::InvalidSchedule)?),
+            };
+            let lot_times = self.lot_times(pool_id)?;
+            let keeper_amount = self.draw_round(pool_id, schedule.keeper_ratio)?;
 
-    impl Lottery {
-        fn new() -> Lottery {
-            Lottery {
-                winning_quantity: 0,
-                total_quantity: 0,
-                reward_ratio: 0,
-                winning_tails: BTreeMap::new(),
-                reward_amount: BTreeMap::new(),
-                user_infos: BTreeMap::new(),
+            match next_trigger {
+                None => { self.schedules.take(&pool_id); },
+                Some(trigger) => {
+                    if let Some(next) = self.schedules.get_mut(&pool_id) {
+                        next.trigger = trigger;
+                    }
+                },
             }
-        }
-    }
 
-    impl UserInfo {
-        fn new() -> UserInfo {
-            UserInfo {
-                buy_quantity: 0,
-                sections: BTreeMap::new(),
-                reward_amount: BTreeMap::new(),
+            // a keeper reward which can't be paid goes back to the pool.
+            let caller = self.env().caller();
+            let mut keeper_rewards = Vec::new();
+            for (token20, amount) in keeper_amount {
+                if amount == 0 { continue }
+                if psp22_transfer(token20, caller, amount).is_err() {
+                    self.remain_amounts.entry(pool_id).and_modify(|btmap| {
+                        btmap.entry(token20).and_modify(|v| *v += amount).or_insert(amount);
+                    });
+                } else {
+                    self.account_out(token20, amount);
+                    keeper_rewards.push((token20, amount));
+                }
             }
-        }
-    }
 
-    fn modify_user_info(user_info: &mut UserInfo, total_quantity: u128, quantity: u128) {
-        user_info.buy_quantity += quantity;
+            self.env().emit_event(DrawTriggered {
+                pool_id,
+                lot_times,
+                keeper: caller,
+                keeper_rewards,
+            });
+            Ok(())
+        }
+
+        /// Draw the current round and start the next one, `keeper_ratio` (/10000) of the round
+        /// reward is cut for the keeper before the tiers share it. Returns the keeper's amounts.
+        fn draw_round(
+            &mut self,
+            pool_id: u128,
+            keeper_ratio: u128,
+        ) -> Result<BTreeMap<AccountId, u128>, Error> {
+            let config = self.pool_config(pool_id)?;
+            let lot_times = self.lot_times(pool_id)?;
+            let (total_quantity, reward_ratio, seed_round, tier_settings) = {
+                let lot = self.lottery(pool_id, lot_times)?;
+                if lot.seed_round == 0 {
+                    return Err(Error::RoundNotOpen)
+                }
+                if self.env().block_number() <= lot.sales_end {
+                    return Err(Error::SalesNotEnded)
+                }
+                (lot.total_quantity, lot.reward_ratio, lot.seed_round, lot.tier_settings.clone())
+            };
+            if total_quantity < config.min_participants {
+                return Err(Error::BelowMinimumParticipants)
+            }
+            let total_winners: u128 = tier_settings.iter().map(|t| t.winning_quantity).sum();
+            if tier_settings.iter().any(|t| t.winning_quantity == 0) || total_winners >= total_quantity {
+                return Err(Error::InvalidWinQuantity)
+            }
+            // the pool's cap may have changed during the round, check what was actually bought.
+            let max_user_lots = self.max_user_lots.get(&(pool_id, lot_times)).copied().unwrap_or(0);
+            if has_exact_tier(&tier_settings) && max_user_lots > MAX_EXACT_LOTS_PER_USER {
+                return Err(Error::ExactTierUncapped)
+            }
+
+            // every winning lot of a tier gets its nft prize, reserve them all from the escrow.
+            let mut nft_needed: BTreeMap<Id, Balance> = BTreeMap::new();
+            for setting in &tier_settings {
+                for (id, amount) in &setting.nft_prize {
+                    *nft_needed.entry(*id).or_insert(0) += amount.saturating_mul(setting.winning_quantity);
+                }
+            }
+            if !nft_needed.is_empty() {
+                let escrow = self.nft_prizes.get_mut(&pool_id).ok_or(Error::InsufficientNftPrize)?;
+                if nft_needed.iter().any(|(id, amount)| escrow.get(id).copied().unwrap_or(0) < *amount) {
+                    return Err(Error::InsufficientNftPrize)
+                }
+                for (id, amount) in &nft_needed {
+                    if let Some(v) = escrow.get_mut(id) {
+                        *v -= amount;
+                    }
+                }
+                escrow.retain(|_, v| *v > 0);
+            }
+
+            let (reward_amount, keeper_amount) = match self.remain_amounts.entry(pool_id) {
+                Entry::Vacant(_) => return Err(Error::PoolNotFound),
+                Entry::Occupied(mut occupied) => {
+                    let mut reward_amount = BTreeMap::new();
+                    let mut keeper_amount = BTreeMap::new();
+                    let btmap = occupied.get_mut();
+                    for (k, v) in btmap.iter_mut() {
+                        let reward = (*v).saturating_mul(reward_ratio).saturating_div(10000);
+                        *v -= reward;
+                        let keeper = reward.saturating_mul(keeper_ratio) / 10000;
+                        reward_amount.insert(*k, reward - keeper);
+                        keeper_amount.insert(*k, keeper);
+                    }
+                    // drained tokens come back with the next purchase or deposit.
+                    btmap.retain(|_, v| *v > 0);
+                    (reward_amount, keeper_amount)
+                },
+            };
+
+            let mut tiers = Vec::with_capacity(tier_settings.len());
+            let mut allocated: BTreeMap<AccountId, u128> = BTreeMap::new();
+            let last = tier_settings.len() - 1;
+            // the salt is the commit–reveal round. Exact tiers take consecutive position ranges
+            // of one permutation of all lots, in the order of the tiers.
+            let round_seed = if has_exact_tier(&tier_settings) {
+                self.draw_lots.draw_exact(seed_round, total_winners, total_quantity)
+            } else {
+                [0; 32]
+            };
+            let mut offset = 0u128;
+            for (i, setting) in tier_settings.into_iter().enumerate() {
+                let (winning_tails, is_winning_tails, exact_seed) = match setting.draw_mode {
+                    DrawMode::Tails => {
+                        let (tails, is_winning) = self.draw_lots.draw_lots(
+                            seed_round, setting.winning_quantity, total_quantity);
+                        (tails, is_winning, [0; 32])
+                    },
+                    DrawMode::Exact => (BTreeMap::new(), true, round_seed),
+                };
+
+                // the last tier takes what is left, so the tiers add up to the round reward.
+                let mut tier_reward = BTreeMap::new();
+                for (token20, amount) in &reward_amount {
+                    let used = allocated.entry(*token20).or_insert(0);
+                    let reward = if i == last {
+                        amount - *used
+                    } else {
+                        amount.saturating_mul(setting.reward_share) / 10000
+                    };
+                    *used += reward;
+                    tier_reward.insert(*token20, reward);
+                }
+
+                tiers.push(Tier {
+                    winning_quantity: setting.winning_quantity,
+                    reward_share: setting.reward_share,
+                    draw_mode: setting.draw_mode,
+                    winning_tails,
+                    is_winning_tails,
+                    exact_seed,
+                    offset,
+                    reward_amount: tier_reward,
+                    nft_prize: setting.nft_prize,
+                });
+                offset += setting.winning_quantity;
+            }
+
+            let event = RoundDrawn {
+                pool_id,
+                lot_times,
+                total_quantity,
+                reward_ratio,
+                reward_amount: reward_amount.iter().map(|(k, v)| (*k, *v)).collect(),
+                tiers: tiers.len() as u32,
+            };
+            let claim_deadline = match config.claim_period {
+                0 => 0,
+                period => self.env().block_timestamp() + period,
+            };
+            let lot = self.lottery_mut(pool_id, lot_times)?;
+            lot.tiers = tiers;
+            lot.reward_amount = reward_amount;
+            lot.claim_deadline = claim_deadline;
+            self.start_next_round(pool_id, lot_times);
+
+            self.env().emit_event(event);
+            Ok(keeper_amount)
+        }
+
+        /// Deposit PSP22 tokens into the reward pool of `pool_id`, the caller must approve first.
+        #[ink(message)]
+        pub fn deposit_reward(&mut self, pool_id: u128, token20: AccountId, amount: u128) -> Result<(), Error> {
+            if !self.pool_config(pool_id)?.reward_tokens.contains(&token20) {
+                return Err(Error::TokenNotRegistered)
+            }
+            let caller = self.env().caller();
+            let lottery = self.env().account_id();
+            psp22_transfer_from(token20, caller, lottery, amount)?;
+            self.remain_amounts.entry(pool_id).and_modify(|btmap| {
+                btmap.entry(token20).and_modify(|v| *v += amount).or_insert(amount);
+            }).or_insert(BTreeMap::from([(token20, amount)]));
+            self.account_in(token20, amount);
+            self.env().emit_event(RewardDeposited {
+                pool_id,
+                user: caller,
+                token20,
+                amount,
+            });
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn receive_reward(&mut self, pool_id: u128, lot_times: u128, buy_times: u128) -> Result<(), Error> {
+            let caller = self.env().caller();
+            if lot_times == 0 || lot_times > self.lot_times(pool_id)? {
+                return Err(Error::RoundNotFound)
+            }
+            // the last round is the one still selling lots.
+            if lot_times == self.lot_times(pool_id)? {
+                return Err(Error::RoundNotDrawn)
+            }
+            let lot = self.lottery(pool_id, lot_times)?;
+            if lot.cancelled {
+                return Err(Error::RoundCancelled)
+            }
+            if lot.settled || claim_expired(lot.claim_deadline, self.env().block_timestamp()) {
+                return Err(Error::ClaimExpired)
+            }
+            let (start, end, is_received) = self.section_info(pool_id, lot_times, caller, buy_times)?;
+            if is_received {
+                return Err(Error::AlreadyClaimed)
+            }
+
+            // add up the rewards of every tier the section wins in.
+            let mut winning_quantity = 0u128;
+            let mut section_rewards: BTreeMap<AccountId, u128> = BTreeMap::new();
+            let mut nft_rewards: BTreeMap<Id, Balance> = BTreeMap::new();
+            {
+                let lot = self.lottery(pool_id, lot_times)?;
+                for tier in &lot.tiers {
+                    let tier_winners = tier.count_winners(start, end, lot.total_quantity);
+                    if tier_winners == 0 { continue }
+                    winning_quantity += tier_winners;
+                    for (token20, amount) in &tier.reward_amount {
+                        let reward = amount.saturating_mul(tier_winners) / tier.winning_quantity;
+                        *section_rewards.entry(*token20).or_insert(0) += reward;
+                    }
+                    for (id, amount) in &tier.nft_prize {
+                        *nft_rewards.entry(*id).or_insert(0) += amount * tier_winners;
+                    }
+                }
+            }
+
+            let mut user_rewards = Vec::new();
+            for (token20, user_reward) in section_rewards {
+                if user_reward == 0 { continue }
+                if psp22_balance_of(token20, self.env().account_id()) < user_reward {
+                    return Err(Error::InsufficientBalance)
+                }
+                user_rewards.push((token20, user_reward));
+            }
+
+            // the section stays received once the claim is made, a transfer which fails
+            // is owed to the user instead, see `claim_owed`.
+            self.set_section_received(pool_id, lot_times, caller, (start, end), true)?;
+            let nft_rewards: Vec<(Id, Balance)> = nft_rewards.into_iter().collect();
+            let user_infos = self.user_info_mut(pool_id, lot_times, caller)?;
+            for (token20, user_reward) in &user_rewards {
+                user_infos.reward_amount.entry(*token20)
+                    .and_modify(|v| *v += user_reward)
+                    .or_insert(*user_reward);
+            }
+            for (id, amount) in &nft_rewards {
+                user_infos.nft_reward.entry(*id)
+                    .and_modify(|v| *v += amount)
+                    .or_insert(*amount);
+            }
+
+            let lot = self.lottery_mut(pool_id, lot_times)?;
+            lot.claimed_lots += winning_quantity;
+            for (token20, user_reward) in &user_rewards {
+                *lot.claimed_amount.entry(*token20).or_insert(0) += user_reward;
+            }
+            for (id, amount) in &nft_rewards {
+                *lot.claimed_nft.entry(*id).or_insert(0) += amount;
+            }
+            // every winning lot is claimed, the rounding dust goes back to the pool.
+            let fully_claimed = lot.claimed_lots >= lot.total_winners();
+            let (user_rewards, nft_rewards) =
+                self.pay_user(pool_id, lot_times, caller, user_rewards, nft_rewards)?;
+            if fully_claimed {
+                self.settle_round(pool_id, lot_times)?;
+            }
+
+            self.env().emit_event(RewardReceived {
+                pool_id,
+                lot_times,
+                user: caller,
+                section: (start, end),
+                winning_quantity,
+                rewards: user_rewards,
+                nft_rewards,
+            });
+            Ok(())
+        }
+
+        /// Pay what the failed transfers of the caller's claims in a round left owed.
+        /// Owed payments don't expire with the claim period.
+        #[ink(message)]
+        pub fn claim_owed(&mut self, pool_id: u128, lot_times: u128) -> Result<(), Error> {
+            let caller = self.env().caller();
+            let user_info = self.user_info_mut(pool_id, lot_times, caller)?;
+            let tokens: Vec<(AccountId, u128)> = core::mem::take(&mut user_info.owed).into_iter().collect();
+            let nfts: Vec<(Id, Balance)> = core::mem::take(&mut user_info.owed_nft).into_iter().collect();
+            if tokens.is_empty() && nfts.is_empty() {
+                return Err(Error::NothingOwed)
+            }
+            let (tokens, nfts) = self.pay_user(pool_id, lot_times, caller, tokens, nfts)?;
+
+            self.env().emit_event(OwedPaid {
+                pool_id,
+                lot_times,
+                user: caller,
+                tokens,
+                nfts,
+            });
+            Ok(())
+        }
+
+        /// Cancel the current round of a pool once its deadline has passed below the minimum
+        /// participants, and start the next round. Anyone can call it.
+        /// What the round paid into the pool is set aside for the refunds, if the pool
+        /// holds less than that, every participant is refunded in proportion.
+        #[ink(message)]
+        pub fn cancel_round(&mut self, pool_id: u128) -> Result<(), Error> {
+            let lot_times = self.lot_times(pool_id)?;
+            let min_quantity = self.pool_config(pool_id)?.min_participants;
+            let now = self.env().block_timestamp();
+            let (contributed, total_quantity) = {
+                let lot = self.lottery(pool_id, lot_times)?;
+                if lot.deadline == 0 || now <= lot.deadline {
+                    return Err(Error::RoundNotExpired)
+                }
+                if lot.total_quantity >= min_quantity {
+                    return Err(Error::MinimumReached)
+                }
+                (lot.contributed.clone(), lot.total_quantity)
+            };
+
+            let mut refund_amount = BTreeMap::new();
+            if let Some(btmap) = self.remain_amounts.get_mut(&pool_id) {
+                for (token20, amount) in &contributed {
+                    if let Some(remain) = btmap.get_mut(token20) {
+                        let refund = (*amount).min(*remain);
+                        *remain -= refund;
+                        refund_amount.insert(*token20, refund);
+                    }
+                }
+            }
+
+            let event = RoundCancelled {
+                pool_id,
+                lot_times,
+                total_quantity,
+                refund_amount: refund_amount.iter().map(|(k, v)| (*k, *v)).collect(),
+            };
+            let claim_deadline = match self.pool_config(pool_id)?.claim_period {
+                0 => 0,
+                period => now + period,
+            };
+            let lot = self.lottery_mut(pool_id, lot_times)?;
+            lot.cancelled = true;
+            lot.refund_amount = refund_amount;
+            lot.claim_deadline = claim_deadline;
+            self.start_next_round(pool_id, lot_times);
+            self.env().emit_event(event);
+            Ok(())
+        }
+
+        /// Claim the refund of the caller's purchases in a cancelled round.
+        #[ink(message)]
+        pub fn claim_refund(&mut self, pool_id: u128, lot_times: u128) -> Result<(), Error> {
+            let caller = self.env().caller();
+            let refunds = {
+                let lot = self.lottery(pool_id, lot_times)?;
+                if !lot.cancelled {
+                    return Err(Error::RoundNotCancelled)
+                }
+                if lot.settled || claim_expired(lot.claim_deadline, self.env().block_timestamp()) {
+                    return Err(Error::ClaimExpired)
+                }
+                let user_info = self.user_info(pool_id, lot_times, caller)?;
+                if user_info.refunded {
+                    return Err(Error::AlreadyRefunded)
+                }
+                refund_of(user_info, &lot.contributed, &lot.refund_amount)
+            };
+            let buy_quantity = self.user_info(pool_id, lot_times, caller)?.buy_quantity;
+
+            // the refund is final once marked, what fails to transfer is owed, see `claim_owed`.
+            self.user_info_mut(pool_id, lot_times, caller)?.refunded = true;
+            let lot = self.lottery_mut(pool_id, lot_times)?;
+            lot.claimed_lots += buy_quantity;
+            for (token20, refund) in &refunds {
+                *lot.claimed_amount.entry(*token20).or_insert(0) += refund;
+            }
+            // every lot is refunded, the rounding dust goes back to the pool.
+            let fully_claimed = lot.claimed_lots >= lot.total_quantity;
+            let (refunds, _) = self.pay_user(pool_id, lot_times, caller, refunds, Vec::new())?;
+            if fully_claimed {
+                self.settle_round(pool_id, lot_times)?;
+            }
+
+            self.env().emit_event(RefundClaimed {
+                pool_id,
+                lot_times,
+                user: caller,
+                refunds,
+            });
+            Ok(())
+        }
+
+        /// Return what a round left unclaimed to the pool once its claim period is over.
+        /// Anyone can call it.
+        #[ink(message)]
+        pub fn expire_round(&mut self, pool_id: u128, lot_times: u128) -> Result<(), Error> {
+            if lot_times >= self.lot_times(pool_id)? {
+                return Err(Error::RoundNotDrawn)
+            }
+            let lot = self.lottery(pool_id, lot_times)?;
+            if lot.settled {
+                return Err(Error::ClaimExpired)
+            }
+            if !claim_expired(lot.claim_deadline, self.env().block_timestamp()) {
+                return Err(Error::ClaimNotExpired)
+            }
+            self.settle_round(pool_id, lot_times)
+        }
+
+        /// Reconcile the accounting of `token20` with its PSP22 balance: whatever the lottery
+        /// holds above what it owes, e.g. tokens sent to it directly, is added to the pool.
+        /// Returns the amount swept. Refused until every pool is migrated, the funds of legacy
+        /// rounds are only accounted by `migrate_pool`.
+        #[ink(message)]
+        #[modifiers(only_owner)]
+        pub fn sweep_dust(&mut self, pool_id: u128, token20: AccountId) -> Result<u128, Error> {
+            if !self.lotteries.is_empty() {
+                return Err(Error::PoolNotMigrated)
+            }
+            if !self.pool_config(pool_id)?.reward_tokens.contains(&token20) {
+                return Err(Error::TokenNotRegistered)
+            }
+            let balance = psp22_balance_of(token20, self.env().account_id());
+            let dust = balance.saturating_sub(self.accounted_of(token20));
+            if dust > 0 {
+                self.remain_amounts.entry(pool_id).and_modify(|btmap| {
+                    btmap.entry(token20).and_modify(|v| *v += dust).or_insert(dust);
+                }).or_insert(BTreeMap::from([(token20, dust)]));
+                self.account_in(token20, dust);
+            }
+            self.env().emit_event(DustSwept {
+                pool_id,
+                token20,
+                amount: dust,
+            });
+            Ok(dust)
+        }
+
+        /// The PSP22 balance of `token20` and the amount the lottery owes of it, the balance is
+        /// never below what is owed.
+        #[ink(message)]
+        pub fn get_token_accounting(&self, token20: AccountId) -> (u128, u128) {
+            (psp22_balance_of(token20, self.env().account_id()), self.accounted_of(token20))
+        }
+
+        /// Send what a round didn't pay out back to the pool, tokens to `remain_amounts` and
+        /// nft prizes to the escrow.
+        fn settle_round(&mut self, pool_id: u128, lot_times: u128) -> Result<(), Error> {
+            let lot = self.lottery_mut(pool_id, lot_times)?;
+            lot.settled = true;
+            let owed = if lot.cancelled { &lot.refund_amount } else { &lot.reward_amount };
+            let returned: Vec<(AccountId, u128)> = owed.iter()
+                .map(|(token20, amount)| {
+                    let paid = lot.claimed_amount.get(token20).copied().unwrap_or(0);
+                    (*token20, amount.saturating_sub(paid))
+                })
+                .filter(|(_, amount)| *amount > 0)
+                .collect();
+            let mut nft_owed: BTreeMap<Id, Balance> = BTreeMap::new();
+            for tier in &lot.tiers {
+                for (id, amount) in &tier.nft_prize {
+                    *nft_owed.entry(*id).or_insert(0) += amount * tier.winning_quantity;
+                }
+            }
+            let returned_nft: Vec<(Id, Balance)> = nft_owed.into_iter()
+                .map(|(id, amount)| (id, amount.saturating_sub(lot.claimed_nft.get(&id).copied().unwrap_or(0))))
+                .filter(|(_, amount)| *amount > 0)
+                .collect();
+
+            for (token20, amount) in &returned {
+                let amount = *amount;
+                self.remain_amounts.entry(pool_id).and_modify(|btmap| {
+                    btmap.entry(*token20).and_modify(|v| *v += amount).or_insert(amount);
+                }).or_insert(BTreeMap::from([(*token20, amount)]));
+            }
+            for (id, amount) in &returned_nft {
+                let amount = *amount;
+                self.nft_prizes.entry(pool_id).and_modify(|btmap| {
+                    btmap.entry(*id).and_modify(|v| *v += amount).or_insert(amount);
+                }).or_insert(BTreeMap::from([(*id, amount)]));
+            }
+
+            self.env().emit_event(RoundSettled {
+                pool_id,
+                lot_times,
+                returned,
+                returned_nft,
+            });
+            Ok(())
+        }
+
+        /// Transfer tokens and nfts to a user, returns what was paid. What can't be transferred
+        /// is added to the user's owed payments of the round, the lottery keeps accounting it.
+        fn pay_user(
+            &mut self,
+            pool_id: u128,
+            lot_times: u128,
+            user: AccountId,
+            tokens: Vec<(AccountId, u128)>,
+            nfts: Vec<(Id, Balance)>,
+        ) -> Result<(Vec<(AccountId, u128)>, Vec<(Id, Balance)>), Error> {
+            let (mut paid_tokens, mut owed_tokens) = (Vec::new(), Vec::new());
+            for (token20, amount) in tokens {
+                if amount == 0 { continue }
+                if psp22_transfer(token20, user, amount).is_ok() {
+                    self.account_out(token20, amount);
+                    paid_tokens.push((token20, amount));
+                } else {
+                    owed_tokens.push((token20, amount));
+                }
+            }
+            let (mut paid_nfts, mut owed_nfts) = (Vec::new(), Vec::new());
+            if !nfts.is_empty() {
+                let lottery = self.env().account_id();
+                if self.nft.batch_transfer_from(lottery, user, nfts.clone(), Vec::new()).is_ok() {
+                    paid_nfts = nfts;
+                } else {
+                    owed_nfts = nfts;
+                }
+            }
+
+            if !owed_tokens.is_empty() || !owed_nfts.is_empty() {
+                let user_info = self.user_info_mut(pool_id, lot_times, user)?;
+                for (token20, amount) in &owed_tokens {
+                    *user_info.owed.entry(*token20).or_insert(0) += amount;
+                }
+                for (id, amount) in &owed_nfts {
+                    *user_info.owed_nft.entry(*id).or_insert(0) += amount;
+                }
+                self.env().emit_event(PaymentOwed {
+                    pool_id,
+                    lot_times,
+                    user,
+                    tokens: owed_tokens,
+                    nfts: owed_nfts,
+                });
+            }
+            Ok((paid_tokens, paid_nfts))
+        }
+
+        fn accounted_of(&self, token20: AccountId) -> u128 {
+            self.accounted.get(&token20).copied().unwrap_or(0)
+        }
+
+        fn account_in(&mut self, token20: AccountId, amount: u128) {
+            self.accounted.entry(token20).and_modify(|v| *v += amount).or_insert(amount);
+        }
+
+        fn account_out(&mut self, token20: AccountId, amount: u128) {
+            self.accounted.entry(token20).and_modify(|v| *v = v.saturating_sub(amount));
+        }
+
+        fn set_section_received(
+            &mut self,
+            pool_id: u128,
+            lot_times: u128,
+            user: AccountId,
+            section: (u128, u128),
+            is_received: bool,
+        ) -> Result<(), Error> {
+            self.user_info_mut(pool_id, lot_times, user)?
+                .sections
+                .entry(section)
+                .and_modify(|v| *v = is_received);
+            Ok(())
+        }
+
+        fn only_mb_market(&self) -> Result<(), Error> {
+            if self.mystery_box_market != self.env().caller() {
+                return Err(Error::NotMysteryBoxMarket)
+            }
+            Ok(())
+        }
+
+        fn lot_times(&self, pool_id: u128) -> Result<u128, Error> {
+            match self.current_rounds.get(&pool_id) {
+                Some(lot_times) => Ok(*lot_times),
+                None if self.lotteries.contains_key(&pool_id) => Err(Error::PoolNotMigrated),
+                None => Err(Error::PoolNotFound),
+            }
+        }
+
+        /// lot_times++
+        fn start_next_round(&mut self, pool_id: u128, lot_times: u128) {
+            self.rounds.insert((pool_id, lot_times + 1), Lottery::new());
+            self.current_rounds.insert(pool_id, lot_times + 1);
+        }
+
+        fn total_quantity(&self, pool_id: u128, lot_times: u128) -> Result<u128, Error> {
+            Ok(self.lottery(pool_id, lot_times)?.total_quantity)
+        }
+
+        /// How many times does a account buy of current lottery times in current pool.
+        #[ink(message)]
+        pub fn buy_times(&self, pool_id: u128, lot_times: u128, user: AccountId) -> Result<u128, Error> {
+            let _total = self.user_info(pool_id, lot_times, user)?.sections.len() as u128;
+            Ok(_total)
+        }
+
+        fn section_info(
+            &self, 
+            pool_id: u128, 
+            lot_times: u128, 
+            user: AccountId, 
+            buy_times: u128
+        ) -> Result<(u128, u128, bool), Error> {
+            if buy_times == 0 {
+                return Err(Error::BuyTimesOutOfRange)
+            }
+            let _info = self.user_info(pool_id, lot_times, user)?.sections
+                .iter()
+                .nth(buy_times as usize - 1)
+                .ok_or(Error::BuyTimesOutOfRange)?;
+            Ok((_info.0.0, _info.0.1, *_info.1))
+        }
+
+        #[ink(message)]
+        pub fn get_reward_token20s(&self, pool_id: u128) -> Vec<AccountId> {
+            self.pools.get(&pool_id)
+                .map(|pool| pool.config.reward_tokens.clone())
+                .unwrap_or_default()
+        }
+
+        #[ink(message)]
+        pub fn get_pool(&self, pool_id: u128) -> Option<Pool> {
+            self.pools.get(&pool_id).cloned()
+        }
+
+        /// Winning tails of a tier of a drawn round, mapping from tail to its number of digits.
+        #[ink(message)]
+        pub fn get_winning_data(
+            &self,
+            pool_id: u128,
+            lot_times: u128,
+            tier: u32,
+        ) -> Result<BTreeMap<u128, u8>, Error> {
+            Ok(self.lottery(pool_id, lot_times)?.tier(tier)?.winning_tails.clone())
+        }
+
+        /// All prize tiers of a drawn round with their draw results and rewards.
+        #[ink(message)]
+        pub fn get_tiers(&self, pool_id: u128, lot_times: u128) -> Result<Vec<Tier>, Error> {
+            Ok(self.lottery(pool_id, lot_times)?.tiers.clone())
+        }
+
+        #[ink(message)]
+        pub fn get_lottery_reward_ratio(&self, pool_id: u128, lot_times: u128) -> Result<u128, Error> {
+            Ok(self.lottery(pool_id, lot_times)?.reward_ratio)
+        }
+
+        #[ink(message)]
+        pub fn get_round_status(&self, pool_id: u128, lot_times: u128) -> Result<RoundStatus, Error> {
+            let lot = self.lottery(pool_id, lot_times)?;
+            Ok(if lot.cancelled {
+                RoundStatus::Cancelled
+            } else if lot.tiers.is_empty() {
+                RoundStatus::Open
+            } else {
+                RoundStatus::Drawn
+            })
+        }
+
+        /// Deadline of a round to reach the minimum participants, 0 means it has none.
+        #[ink(message)]
+        pub fn get_round_deadline(&self, pool_id: u128, lot_times: u128) -> Result<Timestamp, Error> {
+            Ok(self.lottery(pool_id, lot_times)?.deadline)
+        }
+
+        /// What the user paid into the pool in a round, and whether it has been refunded.
+        #[ink(message)]
+        pub fn get_user_contribution(
+            &self,
+            pool_id: u128,
+            lot_times: u128,
+            user: AccountId,
+        ) -> Result<(BTreeMap<AccountId, u128>, bool), Error> {
+            let user_info = self.user_info(pool_id, lot_times, user)?;
+            Ok((user_info.contributed.clone(), user_info.refunded))
+        }
+
+        #[ink(message)]
+        pub fn get_lottery_reward_amount(
+            &self,
+            pool_id: u128,
+            lot_times: u128,
+            token20: AccountId,
+        ) -> Result<u128, Error> {
+            let lot = self.lottery(pool_id, lot_times)?;
+            Ok(lot.reward_amount.get(&token20).copied().unwrap_or(0))
+        }
+
+        #[ink(message)]
+        pub fn get_user_buy_times(&self, pool_id: u128, lot_times: u128, user: AccountId) -> Result<u128, Error> {
+            let user_info = self.user_info(pool_id, lot_times, user)?;
+            Ok(user_info.sections.len() as u128)
+        }
+
+        /// Rewards the user has received in a round for `token20`.
+        #[ink(message)]
+        pub fn get_user_reward_amount(
+            &self,
+            pool_id: u128,
+            lot_times: u128,
+            user: AccountId,
+            token20: AccountId,
+        ) -> Result<u128, Error> {
+            let user_info = self.user_info(pool_id, lot_times, user)?;
+            Ok(user_info.reward_amount.get(&token20).copied().unwrap_or(0))
+        }
+
+        /// NFTs the user has received in a round, id to amount.
+        #[ink(message)]
+        pub fn get_user_nft_reward(
+            &self,
+            pool_id: u128,
+            lot_times: u128,
+            user: AccountId,
+        ) -> Result<BTreeMap<Id, Balance>, Error> {
+            Ok(self.user_info(pool_id, lot_times, user)?.nft_reward.clone())
+        }
+
+        /// Tokens and NFTs the round owes the user after failed transfers, see `claim_owed`.
+        #[ink(message)]
+        pub fn get_user_owed(
+            &self,
+            pool_id: u128,
+            lot_times: u128,
+            user: AccountId,
+        ) -> Result<(BTreeMap<AccountId, u128>, BTreeMap<Id, Balance>), Error> {
+            let user_info = self.user_info(pool_id, lot_times, user)?;
+            Ok((user_info.owed.clone(), user_info.owed_nft.clone()))
+        }
+
+        /// NFTs escrowed in the pool and not assigned to a round yet.
+        #[ink(message)]
+        pub fn get_nft_prizes(&self, pool_id: u128) -> BTreeMap<Id, Balance> {
+            self.nft_prizes.get(&pool_id).cloned().unwrap_or_default()
+        }
+
+        /// The (start, end) numbers of the user's `buy_times`-th purchase and whether its reward is received.
+        #[ink(message)]
+        pub fn get_user_numbers(
+            &self,
+            pool_id: u128,
+            lot_times: u128,
+            user: AccountId,
+            buy_times: u128,
+        ) -> Result<(u128, u128, bool), Error> {
+            self.section_info(pool_id, lot_times, user, buy_times)
+        }
+
+        #[ink(message)]
+        pub fn min_lottery_addr_quantity(&self, pool_id: u128) -> u128 {
+            self.pools.get(&pool_id).map_or(0, |pool| pool.config.min_participants)
+        }
+
+        /// Number of winning lots of every tier among numbers `start..=end` of a drawn round,
+        /// lets clients preview a purchase's wins before receiving the reward.
+        #[ink(message)]
+        pub fn count_winning_in_range(
+            &self,
+            pool_id: u128,
+            lot_times: u128,
+            start: u128,
+            end: u128,
+        ) -> Result<Vec<u128>, Error> {
+            let lot = self.lottery(pool_id, lot_times)?;
+            Ok(lot.tiers.iter()
+                .map(|tier| tier.count_winners(start, end, lot.total_quantity))
+                .collect())
+        }
+
+        #[ink(message)]
+        pub fn get_draw_mode(&self, pool_id: u128, lot_times: u128, tier: u32) -> Result<DrawMode, Error> {
+            Ok(self.lottery(pool_id, lot_times)?.tier(tier)?.draw_mode)
+        }
+
+        /// True if the winning tails of the tier are the winning lots, false if they are
+        /// the losing ones, which the draw does when more than half of the lots win.
+        #[ink(message)]
+        pub fn is_winning_tails(&self, pool_id: u128, lot_times: u128, tier: u32) -> Result<bool, Error> {
+            Ok(self.lottery(pool_id, lot_times)?.tier(tier)?.is_winning_tails)
+        }
+
+        /// Balance of `token20` held by the lottery contract.
+        #[ink(message)]
+        pub fn get_token20_balance(&self, token20: AccountId) -> u128 {
+            psp22_balance_of(token20, self.env().account_id())
+        }
+
+        fn lottery(&self, pool_id: u128, lot_times: u128) -> Result<&Lottery, Error> {
+            self.lot_times(pool_id)?;
+            self.rounds.get(&(pool_id, lot_times)).ok_or(Error::RoundNotFound)
+        }
+
+        fn lottery_mut(&mut self, pool_id: u128, lot_times: u128) -> Result<&mut Lottery, Error> {
+            self.lot_times(pool_id)?;
+            self.rounds.get_mut(&(pool_id, lot_times)).ok_or(Error::RoundNotFound)
+        }
+
+        fn user_info(&self, pool_id: u128, lot_times: u128, user: AccountId) -> Result<&UserInfo, Error> {
+            self.lottery(pool_id, lot_times)?;
+            self.user_infos.get(&(pool_id, lot_times, user)).ok_or(Error::UserNotInRound)
+        }
+
+        fn pool_config(&self, pool_id: u128) -> Result<PoolConfig, Error> {
+            let pool = self.pools.get(&pool_id).ok_or(Error::PoolNotFound)?;
+            Ok(pool.config.clone())
+        }
+
+        fn user_info_mut(
+            &mut self,
+            pool_id: u128,
+            lot_times: u128,
+            user: AccountId,
+        ) -> Result<&mut UserInfo, Error> {
+            self.lottery(pool_id, lot_times)?;
+            self.user_infos.get_mut(&(pool_id, lot_times, user)).ok_or(Error::UserNotInRound)
+        }
+    }
+
+    impl DrawSchedule {
+        /// The trigger moved on by the interval, None if it leaves the range of its type.
+        fn next_trigger(&self) -> Option<DrawTrigger> {
+            match self.trigger {
+                DrawTrigger::Block(block) => BlockNumber::try_from(self.interval)
+                    .ok()
+                    .and_then(|interval| block.checked_add(interval))
+                    .map(DrawTrigger::Block),
+                DrawTrigger::Timestamp(timestamp) => timestamp.checked_add(self.interval).map(DrawTrigger::Timestamp),
+                quantity => Some(quantity),
+            }
+        }
+    }
+
+    impl Lottery {
+        fn new() -> Lottery {
+            Lottery {
+                total_quantity: 0,
+                reward_ratio: 0,
+                tiers: Vec::new(),
+                reward_amount: BTreeMap::new(),
+                deadline: 0,
+                cancelled: false,
+                contributed: BTreeMap::new(),
+                refund_amount: BTreeMap::new(),
+                claim_deadline: 0,
+                claimed_amount: BTreeMap::new(),
+                claimed_nft: BTreeMap::new(),
+                claimed_lots: 0,
+                settled: false,
+                seed_round: 0,
+                sales_end: 0,
+                tier_settings: Vec::new(),
+            }
+        }
+
+        fn is_selling(&self, block: BlockNumber) -> bool {
+            self.seed_round != 0 && block <= self.sales_end
+        }
+
+        /// Winning lots of all tiers.
+        fn total_winners(&self) -> u128 {
+            self.tiers.iter().map(|tier| tier.winning_quantity).sum()
+        }
+
+        fn tier(&self, tier: u32) -> Result<&Tier, Error> {
+            self.tiers.get(tier as usize).ok_or(Error::TierNotFound)
+        }
+    }
+
+    impl LegacyLottery {
+        /// Convert the round and split it from its users. A drawn legacy round had one tier of
+        /// winning tails sharing the whole reward. `receive_reward` only recorded the rewards
+        /// without transferring them, so they become owed and are paid by `claim_owed`.
+        /// Legacy purchases recorded no payments, they can't be refunded.
+        fn split(self, drawn: bool) -> (Lottery, BTreeMap<AccountId, UserInfo>) {
+            let mut claimed_amount: BTreeMap<AccountId, u128> = BTreeMap::new();
+            let mut users = BTreeMap::new();
+            for (user, legacy) in self.user_infos {
+                for (token20, amount) in &legacy.reward_amount {
+                    *claimed_amount.entry(*token20).or_insert(0) += amount;
+                }
+                users.insert(user, UserInfo {
+                    buy_quantity: legacy.buy_quantity,
+                    sections: legacy.sections,
+                    reward_amount: legacy.reward_amount.clone(),
+                    nft_reward: BTreeMap::new(),
+                    contributed: BTreeMap::new(),
+                    refunded: false,
+                    owed: legacy.reward_amount,
+                    owed_nft: BTreeMap::new(),
+                });
+            }
+            let mut tiers = Vec::new();
+            if drawn {
+                tiers.push(Tier {
+                    winning_quantity: self.winning_quantity,
+                    reward_share: 10000,
+                    draw_mode: DrawMode::Tails,
+                    winning_tails: self.winning_tails,
+                    is_winning_tails: true,
+                    exact_seed: [0; 32],
+                    offset: 0,
+                    reward_amount: self.reward_amount.clone(),
+                    nft_prize: Vec::new(),
+                });
+            }
+            let lot = Lottery {
+                total_quantity: self.total_quantity,
+                reward_ratio: self.reward_ratio,
+                tiers,
+                reward_amount: self.reward_amount,
+                deadline: 0,
+                cancelled: false,
+                contributed: BTreeMap::new(),
+                refund_amount: BTreeMap::new(),
+                claim_deadline: 0,
+                claimed_amount,
+                claimed_nft: BTreeMap::new(),
+                claimed_lots: 0,
+                settled: false,
+                seed_round: 0,
+                sales_end: 0,
+                tier_settings: Vec::new(),
+            };
+            (lot, users)
+        }
+    }
+
+    impl Tier {
+        /// Number of winning lots among numbers `start..=end`, whichever mode the tier was drawn with.
+        /// Exact mode checks the lots one by one, each check is O(1) and reads no storage,
+        /// the lots of a user are capped by `MAX_EXACT_LOTS_PER_USER`.
+        fn count_winners(&self, start: u128, end: u128, total_quantity: u128) -> u128 {
+            if self.draw_mode == DrawMode::Exact {
+                let permutation = Permutation::new(&self.exact_seed, total_quantity);
+                return count_in_range(&permutation, start, end, self.offset, self.winning_quantity)
+            }
+            let matched = count_winning_lots(start, end, &self.winning_tails);
+            if self.is_winning_tails {
+                matched
+            } else {
+                (end + 1).saturating_sub(start) - matched
+            }
+        }
+    }
+
+    impl UserInfo {
+        fn new() -> UserInfo {
+            UserInfo {
+                buy_quantity: 0,
+                sections: BTreeMap::new(),
+                reward_amount: BTreeMap::new(),
+                nft_reward: BTreeMap::new(),
+                contributed: BTreeMap::new(),
+                refunded: false,
+                owed: BTreeMap::new(),
+                owed_nft: BTreeMap::new(),
+            }
+        }
+    }
+
+    /// A claim deadline of 0 never expires.
+    fn claim_expired(claim_deadline: Timestamp, now: Timestamp) -> bool {
+        claim_deadline != 0 && now > claim_deadline
+    }
+
+    /// The user's share of the refunds of a cancelled round, in proportion to what they paid.
+    fn refund_of(
+        user_info: &UserInfo,
+        contributed: &BTreeMap<AccountId, u128>,
+        refund_amount: &BTreeMap<AccountId, u128>,
+    ) -> Vec<(AccountId, u128)> {
+        let mut refunds = Vec::new();
+        for (token20, paid) in &user_info.contributed {
+            let total_paid = contributed.get(token20).copied().unwrap_or(0);
+            let refund_total = refund_amount.get(token20).copied().unwrap_or(0);
+            if total_paid == 0 { continue }
+            let refund = paid.saturating_mul(refund_total) / total_paid;
+            if refund > 0 {
+                refunds.push((*token20, refund));
+            }
+        }
+        refunds
+    }
+
+    fn validate_pool_config(config: &PoolConfig) -> Result<(), Error> {
+        if config.min_reward_ratio > config.max_reward_ratio || config.max_reward_ratio > 10000 {
+            return Err(Error::InvalidPoolConfig)
+        }
+        if config.reward_ratio < config.min_reward_ratio || config.reward_ratio > config.max_reward_ratio {
+            return Err(Error::InvalidPoolConfig)
+        }
+        let total_share: u128 = config.tiers.iter().map(|t| t.reward_share).sum();
+        if !config.tiers.is_empty() && (total_share != 10000 || !tiers_are_disjoint(&config.tiers)) {
+            return Err(Error::InvalidPoolConfig)
+        }
+        if has_exact_tier(&config.tiers)
+            && (config.max_lots_per_user == 0 || config.max_lots_per_user > MAX_EXACT_LOTS_PER_USER)
+        {
+            return Err(Error::ExactTierUncapped)
+        }
+        Ok(())
+    }
+
+    /// Check the reward ratio and tiers a round is drawn with against the pool config.
+    fn check_draw_settings(config: &PoolConfig, reward_ratio: u128, tiers: &[TierSetting]) -> Result<(), Error> {
+        if reward_ratio < config.min_reward_ratio || reward_ratio > config.max_reward_ratio {
+            return Err(Error::RewardRatioOutOfBounds)
+        }
+        let total_share: u128 = tiers.iter().map(|t| t.reward_share).sum();
+        if tiers.is_empty() || total_share != 10000 || !tiers_are_disjoint(tiers) {
+            return Err(Error::InvalidTiers)
+        }
+        if tiers.iter().any(|t| t.winning_quantity == 0) {
+            return Err(Error::InvalidWinQuantity)
+        }
+        if has_exact_tier(tiers)
+            && (config.max_lots_per_user == 0 || config.max_lots_per_user > MAX_EXACT_LOTS_PER_USER)
+        {
+            return Err(Error::ExactTierUncapped)
+        }
+        Ok(())
+    }
+
+    fn has_exact_tier(tiers: &[TierSetting]) -> bool {
+        tiers.iter().any(|t| t.draw_mode == DrawMode::Exact)
+    }
+
+    /// Only Exact tiers can share the lots of a round without a lot winning twice.
+    fn tiers_are_disjoint(tiers: &[TierSetting]) -> bool {
+        tiers.len() <= 1 || tiers.iter().all(|t| t.draw_mode == DrawMode::Exact)
+    }
+
+    fn modify_user_info(
+        user_info: &mut UserInfo,
+        total_quantity: u128,
+        quantity: u128,
+        token20: AccountId,
+        amount: u128,
+    ) {
+        user_info.buy_quantity += quantity;
         user_info.sections.insert((total_quantity - quantity + 1, total_quantity), false);
+        user_info.contributed.entry(token20).and_modify(|v| *v += amount).or_insert(amount);
+    }
+
+    fn psp22_balance_of(token20: AccountId, owner: AccountId) -> u128 {
+        build_call::<ink_env::DefaultEnvironment>()
+            .callee(token20)
+            .gas_limit(0)
+            .transferred_value(0)
+            .exec_input(
+                ExecutionInput::new(Selector::new(PSP22_BALANCE_OF_SELECTOR))
+                    .push_arg(owner)
+            )
+            .returns::<ReturnType<u128>>()
+            .fire()
+            .unwrap_or(0)
+    }
+
+    fn psp22_transfer(token20: AccountId, to: AccountId, value: u128) -> Result<(), Error> {
+        build_call::<ink_env::DefaultEnvironment>()
+            .callee(token20)
+            .gas_limit(0)
+            .transferred_value(0)
+            .exec_input(
+                ExecutionInput::new(Selector::new(PSP22_TRANSFER_SELECTOR))
+                    .push_arg(to)
+                    .push_arg(value)
+                    .push_arg(Vec::<u8>::new())
+            )
+            .returns::<ReturnType<Result<(), PSP22Error>>>()
+            .fire()
+            .map_err(|_| Error::TransferFailed)?
+            .map_err(|_| Error::TransferFailed)
+    }
+
+    fn psp22_transfer_from(
+        token20: AccountId,
+        from: AccountId,
+        to: AccountId,
+        value: u128,
+    ) -> Result<(), Error> {
+        build_call::<ink_env::DefaultEnvironment>()
+            .callee(token20)
+            .gas_limit(0)
+            .transferred_value(0)
+            .exec_input(
+                ExecutionInput::new(Selector::new(PSP22_TRANSFER_FROM_SELECTOR))
+                    .push_arg(from)
+                    .push_arg(to)
+                    .push_arg(value)
+                    .push_arg(Vec::<u8>::new())
+            )
+            .returns::<ReturnType<Result<(), PSP22Error>>>()
+            .fire()
+            .map_err(|_| Error::TransferFailed)?
+            .map_err(|_| Error::TransferFailed)
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use ink_lang as ink;
+
+        // field for field the baseline `UserInfo` and `Lottery`.
+        type BaselineUserInfo = (u128, BTreeMap<(u128, u128), bool>, BTreeMap<AccountId, u128>);
+        type BaselineLottery = (
+            u128,  // winning_quantity
+            u128,  // total_quantity
+            u128,  // reward_ratio
+            BTreeMap<u128, u8>,  // winning_tails
+            BTreeMap<AccountId, u128>,  // reward_amount
+            BTreeMap<AccountId, BaselineUserInfo>,  // user_infos
+        );
+
+        #[ink::test]
+        fn migrates_baseline_rounds() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>()
+                .expect("off-chain accounts");
+            let mut lottery = LuckyLottery::new(accounts.django);
+            let token20 = accounts.frank;
+
+            // lots 3 and 13 win, bob received his half of the reward.
+            let drawn: BaselineLottery = (
+                2,
+                20,
+                5000,
+                BTreeMap::from([(3, 1)]),
+                BTreeMap::from([(token20, 400)]),
+                BTreeMap::from([
+                    (accounts.bob, (10, BTreeMap::from([((1, 10), true)]), BTreeMap::from([(token20, 200)]))),
+                    (accounts.charlie, (10, BTreeMap::from([((11, 20), false)]), BTreeMap::new())),
+                ]),
+            );
+            let selling: BaselineLottery = (
+                0,
+                5,
+                0,
+                BTreeMap::new(),
+                BTreeMap::new(),
+                BTreeMap::from([(accounts.bob, (5, BTreeMap::from([((1, 5), false)]), BTreeMap::new()))]),
+            );
+            let encoded = BTreeMap::from([(1u128, drawn), (2u128, selling)]).encode();
+            let legacy = BTreeMap::<u128, LegacyLottery>::decode(&mut &encoded[..])
+                .expect("the baseline layout decodes");
+            lottery.lotteries.insert(7, legacy);
+            lottery.remain_amounts.insert(7, BTreeMap::from([(token20, 400)]));
+
+            assert_eq!(lottery.migrate_pool(7, 1), Ok(1));
+            assert_eq!(lottery.lot_times(7), Err(Error::PoolNotMigrated));
+            assert_eq!(lottery.migrate_pool(7, 1), Ok(0));
+            assert_eq!(lottery.lot_times(7), Ok(2));
+
+            let lot = lottery.lottery(7, 1).expect("drawn round");
+            assert_eq!(lot.tiers.len(), 1);
+            assert!(lot.tiers[0].is_winning_tails);
+            assert_eq!(lot.tiers[0].count_winners(1, 20, lot.total_quantity), 2);
+            assert_eq!(lot.claimed_amount, BTreeMap::from([(token20, 200)]));
+            assert!(lottery.lottery(7, 2).expect("selling round").tiers.is_empty());
+
+            assert_eq!(
+                lottery.get_user_owed(7, 1, accounts.bob),
+                Ok((BTreeMap::from([(token20, 200)]), BTreeMap::new())),
+            );
+            assert_eq!(lottery.get_user_numbers(7, 1, accounts.charlie, 1), Ok((11, 20, false)));
+            assert_eq!(lottery.get_user_numbers(7, 2, accounts.bob, 1), Ok((1, 5, false)));
+            // the pool and the drawn round's reward.
+            assert_eq!(lottery.accounted_of(token20), 800);
+        }
     }
 }
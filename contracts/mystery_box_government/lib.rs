@@ -0,0 +1,456 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+pub use self::mystery_box_government::MysteryBoxGovernment;
+
+#[brush::contract]
+mod mystery_box_government {
+    use nft_factory::NftFactory;
+    use mystery_box_market::MysteryBoxMarket;
+    use brush::modifiers;
+    use ownable::traits::*;
+
+    use ink_env::call::FromAccountId;
+    use ink_storage::{
+        lazy::Lazy,
+        collections::HashMap as StorageHashMap,
+        collections::hashmap::Entry,
+        traits::{SpreadLayout, PackedLayout},
+    };
+    use ink_prelude::string::String;
+    use scale::{Encode, Decode};
+
+    #[derive(Debug, PartialEq, Eq, Encode, Decode)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    pub enum Error {
+        Custom(String),
+        NotVoter,
+        ProposalNotFound,
+        AlreadyVoted,
+        VotingClosed,
+        NotExecutable,
+        AlreadyFinished,
+        MintFailed,
+        StockFailed,
+        ChangeNotFound,
+        /// The timelock of the queued change hasn't passed yet.
+        ChangeNotReady,
+    }
+
+    impl From<OwnableError> for Error {
+        fn from(err: OwnableError) -> Self {
+            match err {
+                OwnableError::CallerIsNotOwner => Error::Custom(String::from("O::CallerIsNotOwner")),
+                OwnableError::NewOwnerIsZero => Error::Custom(String::from("O::NewOwnerIsZero")),
+            }
+        }
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Encode, Decode)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    pub enum ProposalState {
+        Active,  // voting is open
+        Defeated,  // not enough yes votes when voting closed
+        Queued,  // passed, waiting for the timelock
+        Executable,
+        Executed,
+        Canceled,
+    }
+
+    #[ink(event)]
+    pub struct ProposalCreated {
+        #[ink(topic)]
+        proposal_id: u128,
+        #[ink(topic)]
+        proposer: AccountId,
+        pool_id: u128,
+        vote_end: BlockNumber,
+    }
+
+    #[ink(event)]
+    pub struct Voted {
+        #[ink(topic)]
+        proposal_id: u128,
+        #[ink(topic)]
+        voter: AccountId,
+        approve: bool,
+    }
+
+    #[ink(event)]
+    pub struct ProposalExecuted {
+        #[ink(topic)]
+        proposal_id: u128,
+        #[ink(topic)]
+        origin_id: [u8; 32],
+    }
+
+    #[ink(event)]
+    pub struct ProposalCanceled {
+        #[ink(topic)]
+        proposal_id: u128,
+    }
+
+    #[ink(event)]
+    pub struct ChangeQueued {
+        #[ink(topic)]
+        change_id: u128,
+        change: GovernanceChange,
+        eta: BlockNumber,
+    }
+
+    #[ink(event)]
+    pub struct ChangeApplied {
+        #[ink(topic)]
+        change_id: u128,
+    }
+
+    #[ink(event)]
+    pub struct ChangeCanceled {
+        #[ink(topic)]
+        change_id: u128,
+    }
+
+    /// A change of the voters, the voting parameters or the market, it goes through the same
+    /// timelock as the proposals, so voters can see it coming.
+    #[derive(Debug, Clone, PartialEq, Eq, Encode, Decode, SpreadLayout, PackedLayout)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    pub enum GovernanceChange {
+        Voter(AccountId, bool),
+        VotingParams {
+            quorum: u128,
+            voting_period: BlockNumber,
+            timelock_delay: BlockNumber,
+        },
+        /// The market executed proposals mint to.
+        Market(AccountId),
+    }
+
+    /// A proposal to mint a work into the boxes of a market pool.
+    #[derive(Debug, Clone, PartialEq, Eq, Encode, Decode, SpreadLayout, PackedLayout)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    pub struct MintProposal {
+        proposer: AccountId,
+        pool_id: u128,
+        uri: String,
+        num_full_copies: u128,
+        num_split_full_copies: u128,
+        num_fragments: u128,
+        yes_votes: u128,
+        no_votes: u128,
+        quorum: u128,  // minimum yes votes, fixed when the proposal is created
+        created: BlockNumber,  // only voters added by this block can vote
+        vote_end: BlockNumber,  // last block of voting
+        eta: BlockNumber,  // first block the proposal can be executed
+        executed: bool,
+        canceled: bool,
+    }
+
+    #[ink(storage)]
+    #[derive(Default, OwnableStorage)]
+    pub struct MysteryBoxGovernment {
+        #[OwnableStorageField]
+        ownable: OwnableData,
+        /// nft factory contract
+        nft: Lazy<NftFactory>,
+        /// mystery box market contract, receives all minted works.
+        market: Lazy<MysteryBoxMarket>,
+        market_account: AccountId,
+        /// Mapping from `AccountId` to a bool value.
+        voters: StorageHashMap<AccountId, bool>,
+        /// Minimum yes votes for a proposal to pass.
+        quorum: u128,
+        /// Number of blocks a proposal is open for voting.
+        voting_period: BlockNumber,
+        /// Number of blocks between the end of voting and execution.
+        timelock_delay: BlockNumber,
+        proposal_id: u128,
+        proposals: StorageHashMap<u128, MintProposal>,
+        /// Mapping from (proposal_id, voter) to the vote.
+        votes: StorageHashMap<(u128, AccountId), bool>,
+        /// Mapping from voter to the block it was added at.
+        voter_since: StorageHashMap<AccountId, BlockNumber>,
+        change_id: u128,
+        /// Mapping from change_id to the queued change and its first block to apply.
+        changes: StorageHashMap<u128, (GovernanceChange, BlockNumber)>,
+    }
+
+    impl Ownable for MysteryBoxGovernment {}
+
+    impl MysteryBoxGovernment {
+        #[ink(constructor)]
+        pub fn new(
+            nft_factory: AccountId,
+            quorum: u128,
+            voting_period: BlockNumber,
+            timelock_delay: BlockNumber,
+        ) -> Self {
+            let mut instance = Self::default();
+            let caller = Self::env().caller();
+            instance._init_with_owner(caller);
+            instance.voters.insert(caller, true);
+            instance.voter_since.insert(caller, Self::env().block_number());
+
+            let nft: NftFactory = FromAccountId::from_account_id(nft_factory);
+            instance.nft = Lazy::new(nft);
+            instance.quorum = quorum;
+            instance.voting_period = voting_period;
+            instance.timelock_delay = timelock_delay;
+            instance
+        }
+
+        /// The market is deployed with this contract's address, so it is set afterwards.
+        /// Queue the market change, see `apply_change`. Returns the change id.
+        #[ink(message)]
+        #[modifiers(only_owner)]
+        pub fn set_market(&mut self, market: AccountId) -> Result<u128, OwnableError> {
+            Ok(self.queue_change(GovernanceChange::Market(market)))
+        }
+
+        /// Queue adding or removing a voter, see `apply_change`. Returns the change id.
+        #[ink(message)]
+        #[modifiers(only_owner)]
+        pub fn set_voter(&mut self, voter: AccountId, is_voter: bool) -> Result<u128, OwnableError> {
+            Ok(self.queue_change(GovernanceChange::Voter(voter, is_voter)))
+        }
+
+        /// Queue new voting parameters, see `apply_change`. Returns the change id.
+        #[ink(message)]
+        #[modifiers(only_owner)]
+        pub fn set_voting_params(
+            &mut self,
+            quorum: u128,
+            voting_period: BlockNumber,
+            timelock_delay: BlockNumber,
+        ) -> Result<u128, OwnableError> {
+            Ok(self.queue_change(GovernanceChange::VotingParams { quorum, voting_period, timelock_delay }))
+        }
+
+        /// Apply a queued change once its timelock has passed. Anyone can apply.
+        #[ink(message)]
+        pub fn apply_change(&mut self, change_id: u128) -> Result<(), Error> {
+            let (_, eta) = self.changes.get(&change_id).ok_or(Error::ChangeNotFound)?;
+            if self.env().block_number() < *eta {
+                return Err(Error::ChangeNotReady)
+            }
+            let (change, _) = self.changes.take(&change_id).ok_or(Error::ChangeNotFound)?;
+            match change {
+                GovernanceChange::Voter(voter, is_voter) => {
+                    if is_voter && !self.is_voter(voter) {
+                        self.voter_since.insert(voter, self.env().block_number());
+                    }
+                    self.voters.insert(voter, is_voter);
+                },
+                GovernanceChange::VotingParams { quorum, voting_period, timelock_delay } => {
+                    self.quorum = quorum;
+                    self.voting_period = voting_period;
+                    self.timelock_delay = timelock_delay;
+                },
+                GovernanceChange::Market(market) => {
+                    let _market: MysteryBoxMarket = FromAccountId::from_account_id(market);
+                    self.market = Lazy::new(_market);
+                    self.market_account = market;
+                },
+            }
+
+            self.env().emit_event(ChangeApplied { change_id });
+            Ok(())
+        }
+
+        #[ink(message)]
+        #[modifiers(only_owner)]
+        pub fn cancel_change(&mut self, change_id: u128) -> Result<(), Error> {
+            self.changes.take(&change_id).ok_or(Error::ChangeNotFound)?;
+            self.env().emit_event(ChangeCanceled { change_id });
+            Ok(())
+        }
+
+        /// A queued change and its first block to apply.
+        #[ink(message)]
+        pub fn get_change(&self, change_id: u128) -> Option<(GovernanceChange, BlockNumber)> {
+            self.changes.get(&change_id).cloned()
+        }
+
+        #[ink(message)]
+        pub fn propose_mint(
+            &mut self,
+            pool_id: u128,
+            uri: String,
+            num_full_copies: u128,
+            num_split_full_copies: u128,
+            num_fragments: u128,
+        ) -> Result<u128, Error> {
+            let caller = self.env().caller();
+            if !self.is_voter(caller) {
+                return Err(Error::NotVoter)
+            }
+            let created = self.env().block_number();
+            let vote_end = created + self.voting_period;
+            let proposal = MintProposal {
+                proposer: caller,
+                pool_id,
+                uri,
+                num_full_copies,
+                num_split_full_copies,
+                num_fragments,
+                yes_votes: 0,
+                no_votes: 0,
+                quorum: self.quorum,
+                created,
+                vote_end,
+                eta: vote_end + self.timelock_delay,
+                executed: false,
+                canceled: false,
+            };
+            // all id starts from 1.
+            self.proposal_id += 1;
+            let proposal_id = self.proposal_id;
+            self.proposals.insert(proposal_id, proposal);
+
+            self.env().emit_event(ProposalCreated {
+                proposal_id,
+                proposer: caller,
+                pool_id,
+                vote_end,
+            });
+            Ok(proposal_id)
+        }
+
+        #[ink(message)]
+        pub fn vote(&mut self, proposal_id: u128, approve: bool) -> Result<(), Error> {
+            let caller = self.env().caller();
+            if !self.is_voter(caller) {
+                return Err(Error::NotVoter)
+            }
+            if self.proposal_state(proposal_id) != Some(ProposalState::Active) {
+                return match self.proposals.get(&proposal_id) {
+                    None => Err(Error::ProposalNotFound),
+                    Some(_) => Err(Error::VotingClosed),
+                }
+            }
+            // voters added after the proposal was created don't vote on it.
+            let created = self.proposals.get(&proposal_id).ok_or(Error::ProposalNotFound)?.created;
+            if self.voter_since.get(&caller).map_or(true, |since| *since > created) {
+                return Err(Error::NotVoter)
+            }
+            match self.votes.entry((proposal_id, caller)) {
+                Entry::Vacant(vacant) => { vacant.insert(approve); },
+                Entry::Occupied(_) => return Err(Error::AlreadyVoted),
+            }
+            let proposal = self.proposals.get_mut(&proposal_id).ok_or(Error::ProposalNotFound)?;
+            if approve {
+                proposal.yes_votes += 1;
+            } else {
+                proposal.no_votes += 1;
+            }
+
+            self.env().emit_event(Voted {
+                proposal_id,
+                voter: caller,
+                approve,
+            });
+            Ok(())
+        }
+
+        /// Mint the work of a passed proposal to the market and stock it into the pool.
+        /// Anyone can execute once the timelock has passed.
+        #[ink(message)]
+        pub fn execute(&mut self, proposal_id: u128) -> Result<(), Error> {
+            match self.proposal_state(proposal_id) {
+                None => return Err(Error::ProposalNotFound),
+                Some(ProposalState::Executable) => (),
+                Some(_) => return Err(Error::NotExecutable),
+            }
+            let proposal = self.proposals.get_mut(&proposal_id).ok_or(Error::ProposalNotFound)?;
+            proposal.executed = true;
+            let (pool_id, uri) = (proposal.pool_id, proposal.uri.clone());
+            let (num_full_copies, num_split_full_copies, num_fragments) = (
+                proposal.num_full_copies, proposal.num_split_full_copies, proposal.num_fragments);
+
+            let (origin_id, mut items) = self.nft
+                .mint(self.market_account, uri, num_full_copies, num_split_full_copies, num_fragments)
+                .map_err(|_| Error::MintFailed)?;
+            if num_full_copies > 0 {
+                items.push((origin_id, num_full_copies));
+            }
+            self.market
+                .stock_box(pool_id, origin_id, items)
+                .map_err(|_| Error::StockFailed)?;
+
+            self.env().emit_event(ProposalExecuted {
+                proposal_id,
+                origin_id,
+            });
+            Ok(())
+        }
+
+        /// The proposer or the owner can cancel a proposal before it is executed.
+        #[ink(message)]
+        pub fn cancel(&mut self, proposal_id: u128) -> Result<(), Error> {
+            let caller = self.env().caller();
+            let owner = self.owner();
+            let proposal = self.proposals.get_mut(&proposal_id).ok_or(Error::ProposalNotFound)?;
+            if caller != proposal.proposer && caller != owner {
+                return Err(Error::Custom(String::from("O::CallerIsNotOwner")))
+            }
+            if proposal.executed || proposal.canceled {
+                return Err(Error::AlreadyFinished)
+            }
+            proposal.canceled = true;
+
+            self.env().emit_event(ProposalCanceled { proposal_id });
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn proposal_state(&self, proposal_id: u128) -> Option<ProposalState> {
+            let proposal = self.proposals.get(&proposal_id)?;
+            let current_block = self.env().block_number();
+            let state = if proposal.canceled {
+                ProposalState::Canceled
+            } else if proposal.executed {
+                ProposalState::Executed
+            } else if current_block <= proposal.vote_end {
+                ProposalState::Active
+            } else if proposal.yes_votes <= proposal.no_votes || proposal.yes_votes < proposal.quorum {
+                ProposalState::Defeated
+            } else if current_block < proposal.eta {
+                ProposalState::Queued
+            } else {
+                ProposalState::Executable
+            };
+            Some(state)
+        }
+
+        #[ink(message)]
+        pub fn get_proposal(&self, proposal_id: u128) -> Option<MintProposal> {
+            self.proposals.get(&proposal_id).cloned()
+        }
+
+        #[ink(message)]
+        pub fn is_voter(&self, user: AccountId) -> bool {
+            // default: false
+            self.voters.get(&user).copied().unwrap_or(false)
+        }
+
+        #[ink(message)]
+        pub fn market(&self) -> AccountId {
+            self.market_account
+        }
+    }
+
+    // private functions
+    impl MysteryBoxGovernment {
+        fn queue_change(&mut self, change: GovernanceChange) -> u128 {
+            let eta = self.env().block_number() + self.timelock_delay;
+            self.change_id += 1;
+            let change_id = self.change_id;
+            self.changes.insert(change_id, (change.clone(), eta));
+
+            self.env().emit_event(ChangeQueued {
+                change_id,
+                change,
+                eta,
+            });
+            change_id
+        }
+    }
+}
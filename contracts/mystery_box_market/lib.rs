@@ -0,0 +1,576 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+pub use self::mystery_box_market::MysteryBoxMarket;
+
+#[brush::contract]
+mod mystery_box_market {
+    use nft_factory::NftFactory;
+    use lucky_lottery::LuckyLottery;
+    use brush::modifiers;
+    use ownable::traits::*;
+    use psp1155::traits::{Id, PSP1155Error, PSP1155Receiver, PSP1155ReceiverError, PSP1155AsDependency};
+    use psp22::traits::PSP22Error;
+    use randomness_provider::{commit_end_of, random_seed_of};
+
+    use ink_env::hash::Blake2x256;
+    use ink_env::call::{
+        build_call,
+        utils::ReturnType,
+        ExecutionInput,
+        FromAccountId,
+        Selector,
+    };
+    use ink_storage::{
+        lazy::Lazy,
+        collections::HashMap as StorageHashMap,
+        collections::hashmap::Entry,
+        traits::{SpreadLayout, PackedLayout},
+    };
+    use ink_prelude::{
+        vec::Vec,
+        string::String,
+    };
+    use scale::{Encode, Decode};
+
+    /// Selectors of the PSP22 messages the market calls on payment tokens.
+    const PSP22_TRANSFER_SELECTOR: [u8; 4] = [0xdb, 0x20, 0xf9, 0xf5];
+    const PSP22_TRANSFER_FROM_SELECTOR: [u8; 4] = [0x54, 0xb3, 0xc7, 0x6e];
+
+    #[derive(Debug, PartialEq, Eq, Encode, Decode)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    pub enum Error {
+        Custom(String),
+        PoolNotFound,
+        PoolClosed,
+        InvalidQuantity,
+        InsufficientBoxes,
+        InvalidFeeRatio,
+        NotGovernment,
+        PaymentFailed,
+        NftTransferFailed,
+        LotteryRejected,
+        OrderNotFound,
+        /// No seed round is set, or its commits have closed, see `set_seed_round`.
+        SeedRoundClosed,
+        /// The commit–reveal round doesn't exist or its commits have closed.
+        SeedRoundUnavailable,
+        /// An earlier order of the pool isn't opened yet.
+        OrderNotReady,
+    }
+
+    impl From<OwnableError> for Error {
+        fn from(err: OwnableError) -> Self {
+            match err {
+                OwnableError::CallerIsNotOwner => Error::Custom(String::from("O::CallerIsNotOwner")),
+                OwnableError::NewOwnerIsZero => Error::Custom(String::from("O::NewOwnerIsZero")),
+            }
+        }
+    }
+
+    impl From<PSP1155Error> for Error {
+        fn from(_: PSP1155Error) -> Self {
+            Error::NftTransferFailed
+        }
+    }
+
+    #[ink(event)]
+    pub struct BoxesStocked {
+        #[ink(topic)]
+        pool_id: u128,
+        #[ink(topic)]
+        origin_id: Id,
+        quantity: u128,
+    }
+
+    #[ink(event)]
+    pub struct BoxesPurchased {
+        #[ink(topic)]
+        pool_id: u128,
+        #[ink(topic)]
+        buyer: AccountId,
+        #[ink(topic)]
+        order_id: u128,
+        quantity: u128,
+        token20: AccountId,
+        pay_amount: Balance,
+        fee_amount: Balance,
+    }
+
+    #[ink(event)]
+    pub struct BoxesOpened {
+        #[ink(topic)]
+        pool_id: u128,
+        #[ink(topic)]
+        buyer: AccountId,
+        #[ink(topic)]
+        order_id: u128,
+        items: Vec<(Id, Balance)>,
+    }
+
+    /// Boxes are sold until `sales_end`, the commit end of `seed_round`, and opened with its seed.
+    #[ink(event)]
+    pub struct SeedRoundSet {
+        #[ink(topic)]
+        seed_round: u32,
+        sales_end: BlockNumber,
+    }
+
+    #[derive(Debug, PartialEq, Eq, Encode, Decode, SpreadLayout, PackedLayout)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    pub struct BoxPool {
+        token20: AccountId,  // payment token
+        price: Balance,  // price of one box
+        lottery_fee_ratio: u128,  // share of each payment forwarded to the lottery, /10000
+        items: Vec<(Id, Balance)>,  // token ids still in the pool to their amounts
+        remain_quantity: u128,  // sum of all item amounts, one item for one box
+        pending_quantity: u128,  // boxes sold and not opened yet, their items are still in `items`
+        sold_quantity: u128,
+        is_open: bool,
+        last_order: u128,  // latest order of the pool, 0 if none
+    }
+
+    /// Boxes bought and waiting to be opened.
+    #[derive(Debug, Clone, PartialEq, Eq, Encode, Decode, SpreadLayout, PackedLayout)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    pub struct Order {
+        pool_id: u128,
+        buyer: AccountId,
+        quantity: u128,
+        items: Vec<(Id, Balance)>,  // drawn items, kept if sending them to the buyer failed
+        randomness_provider: AccountId,  // provider of the seed round when the order was paid
+        seed_round: u32,  // commit–reveal round whose commits were open when the order was paid
+        prev_order: u128,  // previous order of the pool, drawn first, 0 if none
+    }
+
+    #[ink(storage)]
+    #[derive(Default, OwnableStorage)]
+    pub struct MysteryBoxMarket {
+        #[OwnableStorageField]
+        ownable: OwnableData,
+        /// nft factory contract
+        nft: Lazy<NftFactory>,
+        /// lucky lottery contract
+        lottery: Lazy<LuckyLottery>,
+        /// Account of the lucky lottery contract, receives the lottery fee.
+        lottery_account: AccountId,
+        /// The mystery box government contract, the only one allowed to stock boxes.
+        government: AccountId,
+        /// Mapping from pool_id to the boxes on sale.
+        pools: StorageHashMap<u128, BoxPool>,
+        /// The randomness provider contract the boxes are opened with.
+        randomness_provider: AccountId,
+        /// Mapping from order_id to the boxes waiting to be opened.
+        orders: StorageHashMap<u128, Order>,
+        order_id: u128,
+        /// Commit–reveal round of the provider the orders paid now are opened with,
+        /// boxes are sold until its commits close at `sales_end`.
+        seed_round: u32,
+        sales_end: BlockNumber,
+        /// Mapping from (provider, seed round) to the seed the market drew for its orders.
+        round_seeds: StorageHashMap<(AccountId, u32), [u8; 32]>,
+    }
+
+    impl Ownable for MysteryBoxMarket {}
+
+    impl PSP1155Receiver for MysteryBoxMarket {
+        #[ink(message)]
+        fn before_received(
+            &mut self,
+            _operator: AccountId,
+            _from: AccountId,
+            _ids_to_amounts: Vec<(Id, Balance)>,
+            _data: Vec<u8>,
+        ) -> Result<(), PSP1155ReceiverError> {
+            Ok(())
+        }
+    }
+
+    impl MysteryBoxMarket {
+        #[ink(constructor)]
+        pub fn new(
+            nft_factory: AccountId,
+            lucky_lottery: AccountId,
+            government: AccountId,
+            randomness_provider: AccountId,
+        ) -> Self {
+            let mut instance = Self::default();
+            let caller = Self::env().caller();
+            instance._init_with_owner(caller);
+
+            let nft: NftFactory = FromAccountId::from_account_id(nft_factory);
+            instance.nft = Lazy::new(nft);
+            let lottery: LuckyLottery = FromAccountId::from_account_id(lucky_lottery);
+            instance.lottery = Lazy::new(lottery);
+            instance.lottery_account = lucky_lottery;
+            instance.government = government;
+            instance.randomness_provider = randomness_provider;
+            instance
+        }
+
+        /// Open a pool or change its sale settings, the items already stocked are kept.
+        /// The lottery pool with the same id must be created in LuckyLottery first,
+        /// `token20` is added to its reward tokens.
+        #[ink(message)]
+        #[modifiers(only_owner)]
+        pub fn set_pool(
+            &mut self,
+            pool_id: u128,
+            token20: AccountId,
+            price: Balance,
+            lottery_fee_ratio: u128,
+            is_open: bool,
+        ) -> Result<(), Error> {
+            if lottery_fee_ratio > 10000 {
+                return Err(Error::InvalidFeeRatio)
+            }
+            match self.pools.entry(pool_id) {
+                Entry::Vacant(vacant) => {
+                    vacant.insert(BoxPool {
+                        token20,
+                        price,
+                        lottery_fee_ratio,
+                        items: Vec::new(),
+                        remain_quantity: 0,
+                        pending_quantity: 0,
+                        sold_quantity: 0,
+                        is_open,
+                        last_order: 0,
+                    });
+                },
+                Entry::Occupied(mut occupied) => {
+                    let pool = occupied.get_mut();
+                    pool.token20 = token20;
+                    pool.price = price;
+                    pool.lottery_fee_ratio = lottery_fee_ratio;
+                    pool.is_open = is_open;
+                },
+            }
+            self.lottery
+                .add_reward_token(pool_id, token20)
+                .map_err(|_| Error::LotteryRejected)
+        }
+
+        #[ink(message)]
+        #[modifiers(only_owner)]
+        pub fn set_government(&mut self, government: AccountId) -> Result<(), OwnableError> {
+            self.government = government;
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn government(&self) -> AccountId {
+            self.government
+        }
+
+        /// In commit–reveal mode the provider must list the market as a consumer. Orders paid
+        /// before are still opened with the provider of their seed round, and the sales wait
+        /// for a seed round of the new provider.
+        #[ink(message)]
+        #[modifiers(only_owner)]
+        pub fn set_randomness_provider(&mut self, provider: AccountId) -> Result<(), OwnableError> {
+            self.randomness_provider = provider;
+            self.seed_round = 0;
+            self.sales_end = 0;
+            Ok(())
+        }
+
+        /// Sell boxes until the commits of `seed_round` close, their orders are opened with
+        /// the seed of the round, which no one knows while they are paid. The round must not be
+        /// drawn by another consumer of the provider, e.g. the lottery.
+        #[ink(message)]
+        #[modifiers(only_owner)]
+        pub fn set_seed_round(&mut self, seed_round: u32) -> Result<(), Error> {
+            let sales_end = match commit_end_of(self.randomness_provider, seed_round) {
+                Some(commit_end) if seed_round != 0 && commit_end > self.env().block_number() => commit_end,
+                _ => return Err(Error::SeedRoundUnavailable),
+            };
+            self.seed_round = seed_round;
+            self.sales_end = sales_end;
+            self.env().emit_event(SeedRoundSet { seed_round, sales_end });
+            Ok(())
+        }
+
+        /// The current seed round and the last block its boxes are sold in.
+        #[ink(message)]
+        pub fn seed_round(&self) -> (u32, BlockNumber) {
+            (self.seed_round, self.sales_end)
+        }
+
+        #[ink(message)]
+        pub fn randomness_provider(&self) -> AccountId {
+            self.randomness_provider
+        }
+
+        /// Put the copies and fragments of a work minted to this market into the boxes of `pool_id`.
+        /// Only the mystery box government, which owns the right to mint works, can stock boxes.
+        #[ink(message)]
+        pub fn stock_box(
+            &mut self,
+            pool_id: u128,
+            origin_id: Id,
+            items: Vec<(Id, Balance)>,
+        ) -> Result<(), Error> {
+            if self.government != self.env().caller() {
+                return Err(Error::NotGovernment)
+            }
+            let quantity = self.stock_items(pool_id, items)?;
+
+            self.env().emit_event(BoxesStocked {
+                pool_id,
+                origin_id,
+                quantity,
+            });
+            Ok(())
+        }
+
+        /// Buy `quantity` boxes of `pool_id`, each box holds one random item of the pool.
+        /// The lottery fee is sent to the lucky lottery together with the lots of this purchase.
+        /// The boxes are opened later by `open_boxes` with the seed of the current seed round,
+        /// so nobody can see the items before paying. Returns the order id.
+        #[ink(message)]
+        pub fn buy_boxes(&mut self, pool_id: u128, quantity: u128) -> Result<u128, Error> {
+            if quantity == 0 {
+                return Err(Error::InvalidQuantity)
+            }
+            if self.seed_round == 0 || self.env().block_number() > self.sales_end {
+                return Err(Error::SeedRoundClosed)
+            }
+            let buyer = self.env().caller();
+            let market = self.env().account_id();
+            let (token20, pay_amount, fee_amount) = {
+                let pool = self.pools.get(&pool_id).ok_or(Error::PoolNotFound)?;
+                if !pool.is_open {
+                    return Err(Error::PoolClosed)
+                }
+                if pool.remain_quantity - pool.pending_quantity < quantity {
+                    return Err(Error::InsufficientBoxes)
+                }
+                let pay_amount = pool.price.saturating_mul(quantity);
+                let fee_amount = pay_amount.saturating_mul(pool.lottery_fee_ratio) / 10000;
+                (pool.token20, pay_amount, fee_amount)
+            };
+            // the lottery must take the lots before any payment is made.
+            self.lottery
+                .check_lottery_data(pool_id, buyer, quantity, token20)
+                .map_err(|_| Error::LotteryRejected)?;
+
+            // take the payment in one transfer, then forward the fee to the lottery pool.
+            if pay_amount > 0 {
+                psp22_transfer_from(token20, buyer, market, pay_amount)?;
+            }
+            if fee_amount > 0 {
+                psp22_transfer(token20, self.lottery_account, fee_amount)?;
+            }
+
+            self.lottery
+                .add_lottery_data(pool_id, buyer, quantity, token20, fee_amount)
+                .map_err(|_| Error::LotteryRejected)?;
+
+            self.order_id += 1;
+            let order_id = self.order_id;
+            let pool = self.pools.get_mut(&pool_id).ok_or(Error::PoolNotFound)?;
+            pool.pending_quantity += quantity;
+            let prev_order = core::mem::replace(&mut pool.last_order, order_id);
+            self.orders.insert(order_id, Order {
+                pool_id,
+                buyer,
+                quantity,
+                items: Vec::new(),
+                randomness_provider: self.randomness_provider,
+                seed_round: self.seed_round,
+                prev_order,
+            });
+
+            self.env().emit_event(BoxesPurchased {
+                pool_id,
+                buyer,
+                order_id,
+                quantity,
+                token20,
+                pay_amount,
+                fee_amount,
+            });
+            Ok(order_id)
+        }
+
+        /// Open the boxes of an order and send the items to its buyer. Anyone can call it once
+        /// the order's seed round is finalized and the earlier orders of the pool are drawn,
+        /// so the items of every order only depend on the seeds and the purchase order.
+        /// Items which can't be sent stay with the order, opening it again resends them.
+        #[ink(message)]
+        pub fn open_boxes(&mut self, order_id: u128) -> Result<(), Error> {
+            let mut order = self.orders.get(&order_id).cloned().ok_or(Error::OrderNotFound)?;
+            if order.items.is_empty() {
+                let prev_drawn = order.prev_order == 0 || self.orders
+                    .get(&order.prev_order)
+                    .map_or(true, |prev| !prev.items.is_empty());
+                if !prev_drawn {
+                    return Err(Error::OrderNotReady)
+                }
+                let seed = self.round_seed(order.randomness_provider, order.seed_round);
+                order.items = self.draw_items(&order, order_id, seed)?;
+            }
+            let market = self.env().account_id();
+            if self.nft.batch_transfer_from(market, order.buyer, order.items.clone(), Vec::new()).is_err() {
+                self.orders.insert(order_id, order);
+                return Err(Error::NftTransferFailed)
+            }
+            self.orders.take(&order_id);
+
+            self.env().emit_event(BoxesOpened {
+                pool_id: order.pool_id,
+                buyer: order.buyer,
+                order_id,
+                items: order.items,
+            });
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn get_order(&self, order_id: u128) -> Option<Order> {
+            self.orders.get(&order_id).cloned()
+        }
+
+        /// Withdraw sale proceeds kept by the market.
+        #[ink(message)]
+        #[modifiers(only_owner)]
+        pub fn withdraw(&mut self, token20: AccountId, to: AccountId, amount: Balance) -> Result<(), Error> {
+            psp22_transfer(token20, to, amount)
+        }
+
+        #[ink(message)]
+        pub fn get_pool(&self, pool_id: u128) -> Option<BoxPool> {
+            self.pools.get(&pool_id).map(|pool| BoxPool {
+                token20: pool.token20,
+                price: pool.price,
+                lottery_fee_ratio: pool.lottery_fee_ratio,
+                items: pool.items.clone(),
+                remain_quantity: pool.remain_quantity,
+                pending_quantity: pool.pending_quantity,
+                sold_quantity: pool.sold_quantity,
+                is_open: pool.is_open,
+                last_order: pool.last_order,
+            })
+        }
+
+        /// Boxes of the pool still on sale.
+        #[ink(message)]
+        pub fn remain_quantity(&self, pool_id: u128) -> u128 {
+            self.pools.get(&pool_id).map(|pool| pool.remain_quantity - pool.pending_quantity).unwrap_or(0)
+        }
+
+        #[ink(message)]
+        pub fn lottery_account(&self) -> AccountId {
+            self.lottery_account
+        }
+    }
+
+    // private functions
+    impl MysteryBoxMarket {
+        fn stock_items(&mut self, pool_id: u128, items: Vec<(Id, Balance)>) -> Result<u128, Error> {
+            let pool = self.pools.get_mut(&pool_id).ok_or(Error::PoolNotFound)?;
+            let mut quantity = 0u128;
+            for (id, amount) in items {
+                if amount == 0 { continue }
+                quantity += amount;
+                match pool.items.iter_mut().find(|(_id, _)| *_id == id) {
+                    Some((_, _amount)) => *_amount += amount,
+                    None => pool.items.push((id, amount)),
+                }
+            }
+            pool.remain_quantity += quantity;
+            Ok(quantity)
+        }
+
+        /// The seed of a provider's commit–reveal round, drawn by the first order opened with it.
+        fn round_seed(&mut self, provider: AccountId, seed_round: u32) -> [u8; 32] {
+            if let Some(seed) = self.round_seeds.get(&(provider, seed_round)) {
+                return *seed
+            }
+            let seed = random_seed_of(provider, seed_round);
+            self.round_seeds.insert((provider, seed_round), seed);
+            seed
+        }
+
+        /// Pick the items of an order out of its pool, each item of the pool has the same chance.
+        /// Every box hashes the seed of the order's round with the order id and its index.
+        fn draw_items(
+            &mut self,
+            order: &Order,
+            order_id: u128,
+            seed: [u8; 32],
+        ) -> Result<Vec<(Id, Balance)>, Error> {
+            let pool = self.pools.get_mut(&order.pool_id).ok_or(Error::PoolNotFound)?;
+            let mut drawn: Vec<(Id, Balance)> = Vec::new();
+            for i in 0..order.quantity {
+                let hash = Self::env().hash_encoded::<Blake2x256, _>(&(seed, order_id, i));
+                let mut index = <u128>::decode(&mut hash.as_ref())
+                    .expect("get random number failed") % pool.remain_quantity;
+
+                let mut pos = 0usize;
+                for (n, (_, amount)) in pool.items.iter().enumerate() {
+                    if index < *amount {
+                        pos = n;
+                        break;
+                    }
+                    index -= *amount;
+                }
+
+                let id = pool.items[pos].0;
+                pool.items[pos].1 -= 1;
+                if pool.items[pos].1 == 0 {
+                    pool.items.swap_remove(pos);
+                }
+                pool.remain_quantity -= 1;
+                pool.pending_quantity -= 1;
+                pool.sold_quantity += 1;
+
+                match drawn.iter_mut().find(|(_id, _)| *_id == id) {
+                    Some((_, amount)) => *amount += 1,
+                    None => drawn.push((id, 1)),
+                }
+            }
+            Ok(drawn)
+        }
+    }
+
+    fn psp22_transfer(token20: AccountId, to: AccountId, value: Balance) -> Result<(), Error> {
+        build_call::<ink_env::DefaultEnvironment>()
+            .callee(token20)
+            .gas_limit(0)
+            .transferred_value(0)
+            .exec_input(
+                ExecutionInput::new(Selector::new(PSP22_TRANSFER_SELECTOR))
+                    .push_arg(to)
+                    .push_arg(value)
+                    .push_arg(Vec::<u8>::new())
+            )
+            .returns::<ReturnType<Result<(), PSP22Error>>>()
+            .fire()
+            .map_err(|_| Error::PaymentFailed)?
+            .map_err(|_| Error::PaymentFailed)
+    }
+
+    fn psp22_transfer_from(
+        token20: AccountId,
+        from: AccountId,
+        to: AccountId,
+        value: Balance,
+    ) -> Result<(), Error> {
+        build_call::<ink_env::DefaultEnvironment>()
+            .callee(token20)
+            .gas_limit(0)
+            .transferred_value(0)
+            .exec_input(
+                ExecutionInput::new(Selector::new(PSP22_TRANSFER_FROM_SELECTOR))
+                    .push_arg(from)
+                    .push_arg(to)
+                    .push_arg(value)
+                    .push_arg(Vec::<u8>::new())
+            )
+            .returns::<ReturnType<Result<(), PSP22Error>>>()
+            .fire()
+            .map_err(|_| Error::PaymentFailed)?
+            .map_err(|_| Error::PaymentFailed)
+    }
+}
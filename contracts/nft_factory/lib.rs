@@ -1,17 +1,19 @@
 #![cfg_attr(not(feature = "std"), no_std)]
 
-pub use self::nft_factory::NftFactory;
+pub use self::nft_factory::{NftFactory, increase_id};
 
 #[brush::contract]
 mod nft_factory {
-    // TODO: use user_tokens::UserTokens; and add user's tokens' changing corresponding codes.
-    // TODO: override transfer because transfer will take change to UserTokens.
+    use brush::modifiers;
+    use ownable::traits::*;
     use psp1155::traits::*;
+    #[cfg(not(feature = "ink-as-dependency"))]
+    use user_tokens_hook::update_user_tokens_of;
 
     #[cfg(not(feature = "ink-as-dependency"))]
     use ink_storage::collections::HashMap as StorageHashMap;
     #[cfg(not(feature = "ink-as-dependency"))]
-    use ink_prelude::vec;
+    use ink_prelude::{vec, collections::BTreeMap};
 
     use ink_storage::traits::{SpreadLayout, PackedLayout};
     use ink_prelude::{vec::Vec, string::String};
@@ -36,6 +38,16 @@ mod nft_factory {
         quantity: u128,
     }
 
+    /// UserTokens rejected a balance change, the token moved anyway and the owner of
+    /// UserTokens can `reconcile` the account.
+    #[ink(event)]
+    pub struct UserTokensNotifyFailed {
+        #[ink(topic)]
+        account: AccountId,
+        ids_amounts: Vec<(Id, Balance)>,
+        added: bool,
+    }
+
     #[derive(Debug, PartialEq, Eq, Encode, Decode, SpreadLayout, PackedLayout)]
     #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
     pub struct Work {
@@ -43,30 +55,106 @@ mod nft_factory {
         num_fragments: u128,
     }
 
-    #[derive(Default, PSP1155Storage)]
+    #[derive(Default, PSP1155Storage, OwnableStorage)]
     #[ink(storage)]
     pub struct NftFactory {
         #[PSP1155StorageField]
         psp1155: PSP1155Data,
+        #[OwnableStorageField]
+        ownable: OwnableData,
         id: Id,
         uri: String,
         mystery_box_government: AccountId,
         origin_id_to_work: StorageHashMap<Id, Work>,
         is_fragments: StorageHashMap<Id, bool>,
+        /// UserTokens index notified of every balance change, none if it's the zero address.
+        user_tokens: AccountId,
+    }
+
+    /// Transfers are overridden to keep UserTokens up to date, the tokens move with
+    /// PSP1155's own transfer and receiver check.
+    impl PSP1155 for NftFactory {
+        #[ink(message)]
+        fn transfer_from(
+            &mut self,
+            from: AccountId,
+            to: AccountId,
+            id: Id,
+            amount: Balance,
+            _data: Vec<u8>,
+        ) -> Result<(), PSP1155Error> {
+            self.batch_transfer_from(from, to, vec![(id, amount)], _data)
+        }
+
+        #[ink(message)]
+        fn batch_transfer_from(
+            &mut self,
+            from: AccountId,
+            to: AccountId,
+            ids_amounts: Vec<(Id, Balance)>,
+            _data: Vec<u8>,
+        ) -> Result<(), PSP1155Error> {
+            let caller = self.env().caller();
+            if caller != from && !self.is_approved_for_all(from, caller) {
+                return Err(PSP1155Error::NotAllowed)
+            }
+            if to == AccountId::from([0; 32]) {
+                return Err(PSP1155Error::TransferToZeroAddress)
+            }
+            // check every balance first, a failed transfer must not leave the batch half moved.
+            let mut needed: BTreeMap<Id, Balance> = BTreeMap::new();
+            for (id, amount) in &ids_amounts {
+                *needed.entry(*id).or_insert(0) += amount;
+            }
+            if needed.iter().any(|(id, amount)| self.balance_of(from, *id) < *amount) {
+                return Err(PSP1155Error::InsufficientBalance)
+            }
+            self._do_safe_transfer_check(caller, from, to, ids_amounts.clone(), _data)?;
+            for (id, amount) in &ids_amounts {
+                self._transfer_from(from, to, *id, *amount)?;
+            }
+            self._emit_transfer_batch_event(caller, from, to, ids_amounts.clone());
+
+            self.notify_user_tokens(from, &ids_amounts, false);
+            self.notify_user_tokens(to, &ids_amounts, true);
+            Ok(())
+        }
     }
 
-    impl PSP1155 for NftFactory {}
+    impl Ownable for NftFactory {}
 
     impl NftFactory {
         #[ink(constructor)]
-        pub fn new() -> Self {
-            // TODO: need pass the `mysteryBox` contract address argment to replace the `mb_addr`.
+        pub fn new(mystery_box_government: AccountId) -> Self {
             let mut instance = Self::default();
-            let mb_addr: AccountId = [1; 32].into();
-            instance.mystery_box_government = mb_addr;
+            let caller = Self::env().caller();
+            instance._init_with_owner(caller);
+            instance.mystery_box_government = mystery_box_government;
             instance.id = [0; 32];
             instance
         }
+
+        /// Rotate the account allowed to mint works.
+        #[ink(message)]
+        #[modifiers(only_owner)]
+        pub fn set_mystery_box_government(&mut self, government: AccountId) -> Result<(), OwnableError> {
+            self.mystery_box_government = government;
+            Ok(())
+        }
+
+        /// Set the UserTokens contract to notify, NftFactory must be one of its internal callers.
+        /// The zero address turns notifications off.
+        #[ink(message)]
+        #[modifiers(only_owner)]
+        pub fn set_user_tokens(&mut self, user_tokens: AccountId) -> Result<(), OwnableError> {
+            self.user_tokens = user_tokens;
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn user_tokens(&self) -> AccountId {
+            self.user_tokens
+        }
         
         #[ink(message)]
         pub fn mint(
@@ -83,14 +171,14 @@ mod nft_factory {
             );
             // get origin id.
             let mut _origin_id = self.current_id();
-            self.id = self.increase_id(_origin_id, 16); // first half [u8; 16] use for OriginId
+            self.id = increase_id(_origin_id, 16); // first half [u8; 16] use for OriginId
             _origin_id = self.current_id();
 
             // get fragment_ids_amounts
             let mut _fragment_ids_amounts = Vec::new();
             let mut _fragment_id = _origin_id;  // FragmentId consists of OriginId + Index
             for _ in 0.._num_fragments {
-                _fragment_id = self.increase_id(_fragment_id, 32); // second half [u8; 16] use for FragmentId Index 
+                _fragment_id = increase_id(_fragment_id, 32); // second half [u8; 16] use for FragmentId Index 
                 _fragment_ids_amounts.push((_fragment_id, _num_split_full_copies));
                 self.is_fragments.insert(_fragment_id, true);
             }
@@ -104,6 +192,12 @@ mod nft_factory {
                 self.set_uri(_uri.clone());
             }
 
+            let mut _minted = _fragment_ids_amounts.clone();
+            if _num_full_copies > 0 {
+                _minted.push((_origin_id, _num_full_copies));
+            }
+            self.notify_user_tokens(_mb_market_addr, &_minted, true);
+
             // update state variable
             let _work = Work { uri: _uri, num_fragments: _num_fragments };
             self.origin_id_to_work.insert(_origin_id, _work);
@@ -131,15 +225,17 @@ mod nft_factory {
             let mut _fragment_ids_quantities = Vec::new();
             let mut _fragment_id = _origin_id;  // FragmentId consists of OriginId + Index
             for _ in 0.._num_fragments {
-                _fragment_id = self.increase_id(_fragment_id, 32);
+                _fragment_id = increase_id(_fragment_id, 32);
                 if self.balance_of(caller, _fragment_id) < _quantity {
                     return Err(PSP1155Error::InsufficientBalance);
                 }
                 _fragment_ids_quantities.push((_fragment_id, _quantity));
             }
             // burn fragments and mint origin
-            self._burn_from(caller, _fragment_ids_quantities)?;
+            self._burn_from(caller, _fragment_ids_quantities.clone())?;
             self._mint_to(caller, vec![(_origin_id, _quantity)])?;
+            self.notify_user_tokens(caller, &_fragment_ids_quantities, false);
+            self.notify_user_tokens(caller, &[(_origin_id, _quantity)], true);
 
             // TODO: why does it need to set uri, what does the state variable `uri` use for?
             self.set_uri(_uri);
@@ -161,30 +257,58 @@ mod nft_factory {
             *self.is_fragments.get(&_token_id).unwrap_or(&false)
         }
 
+        /// Number of fragments the work of `origin_id` was split into, 0 if there is no such work.
+        #[ink(message)]
+        pub fn num_fragments_of(&self, origin_id: Id) -> u128 {
+            self.origin_id_to_work.get(&origin_id).map_or(0, |work| work.num_fragments)
+        }
+
         #[ink(message)]
         pub fn mystery_box_government(&self) -> AccountId {
             self.mystery_box_government
         }
     }
     
+    // the first 16 u8 elements, use to present complete NFT.
+    // the second 16 u8 elements, use to present fragment NFT's index.
+    // 00000000000000010000000000000000: originId (with 3 fragments)
+    // 00000000000000010000000000000001: fragmentId1
+    // 00000000000000010000000000000002: fragmentId2
+    // 00000000000000010000000000000003: fragmentId3
+    pub fn increase_id(_id: Id, _i: u8) -> Id {
+        let (mut _id, mut _i) = (_id, _i);
+        for _ in 0..15 {
+            if _id[_i as usize -1] != u8::MAX {
+                _id[_i as usize -1] += 1;
+                break;
+            }
+            _i -= 1;
+        }
+        _id
+    }
+
     // private functions
     impl NftFactory {
-        // the first 16 u8 elements, use to present complete NFT.
-        // the second 16 u8 elements, use to present fragment NFT's index.
-        // 00000000000000010000000000000000: originId (with 3 fragments)
-        // 00000000000000010000000000000001: fragmentId1
-        // 00000000000000010000000000000002: fragmentId2
-        // 00000000000000010000000000000003: fragmentId3
-        fn increase_id(&mut self, _id: Id, _i: u8) -> Id {
-            let (mut _id, mut _i) = (_id, _i);
-            for _ in 0..15 {
-                if _id[_i as usize -1] != u8::MAX {
-                    _id[_i as usize -1] += 1;
-                    break;
-                }
-                _i -= 1;
+        /// Tell UserTokens that `account` received (`added`) or lost the amounts of the ids,
+        /// in one call. The index is best-effort, a failed call never blocks the token operation.
+        fn notify_user_tokens(&self, account: AccountId, ids_amounts: &[(Id, Balance)], added: bool) {
+            if self.user_tokens == AccountId::from([0; 32]) {
+                return
+            }
+            let ids_amounts: Vec<(Id, Balance)> = ids_amounts.iter()
+                .filter(|(_, amount)| *amount > 0)
+                .copied()
+                .collect();
+            if ids_amounts.is_empty() {
+                return
+            }
+            if update_user_tokens_of(self.user_tokens, account, ids_amounts.clone(), added).is_err() {
+                self.env().emit_event(UserTokensNotifyFailed {
+                    account,
+                    ids_amounts,
+                    added,
+                });
             }
-            _id
         }
 
         fn current_id(&self) -> Id {
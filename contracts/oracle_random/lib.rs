@@ -0,0 +1,127 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+pub use self::oracle_random::OracleRandom;
+use ink_lang as ink;
+
+/// A randomness provider fed by an off-chain oracle. The oracle pushes a fresh seed
+/// before each draw, and numbers are derived from that seed only, the caller's salt is ignored.
+/// Only the consumers allowed by the owner can draw.
+#[ink::contract]
+mod oracle_random {
+    use randomness_provider::RandomnessProvider;
+    use ink_storage::collections::HashMap as StorageHashMap;
+    #[cfg(not(feature = "ink-as-dependency"))]
+    use scale::Decode;
+    #[cfg(not(feature = "ink-as-dependency"))]
+    use ink_env::hash::Blake2x256;
+
+    #[derive(Debug, PartialEq, Eq, scale::Encode, scale::Decode)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    pub enum Error {
+        CallerIsNotOwner,
+        CallerIsNotOracle,
+    }
+
+    /// The seed itself isn't published, the consumers draw from it first.
+    #[ink(event)]
+    pub struct SeedFulfilled {
+        #[ink(topic)]
+        round: u64,
+    }
+
+    #[derive(Default)]
+    #[ink(storage)]
+    pub struct OracleRandom {
+        owner: AccountId,
+        oracle: AccountId,
+        /// Seed pushed by the oracle, zero until the first fulfill.
+        seed: [u8; 32],
+        round: u64,
+        /// Numbers generated from the current seed.
+        nonce: u64,
+        random_number: u32,
+        /// Contracts allowed to draw, e.g. DrawLots. Every draw moves the nonce on.
+        consumers: StorageHashMap<AccountId, bool>,
+    }
+
+    impl RandomnessProvider for OracleRandom {
+        #[ink(message)]
+        fn random(&mut self, _salt: u32) -> u32 {
+            self.random_seed(_salt);
+            self.random_number
+        }
+
+        #[ink(message)]
+        fn random_seed(&mut self, _salt: u32) -> [u8; 32] {
+            assert!(self.is_consumer(self.env().caller()), "caller is not a randomness consumer");
+            assert!(self.seed != [0; 32], "oracle hasn't fulfilled a seed");
+            self.nonce += 1;
+            let hash = self.env().hash_encoded::<Blake2x256, _>(&(self.seed, self.nonce));
+            self.random_number = <u32>::decode(&mut hash.as_ref()).expect("get random number failed");
+            hash
+        }
+    }
+
+    impl OracleRandom {
+        #[ink(constructor)]
+        pub fn new(oracle: AccountId) -> Self {
+            let mut instance = Self::default();
+            instance.owner = Self::env().caller();
+            instance.oracle = oracle;
+            instance
+        }
+
+        #[ink(message)]
+        pub fn set_oracle(&mut self, oracle: AccountId) -> Result<(), Error> {
+            if self.env().caller() != self.owner {
+                return Err(Error::CallerIsNotOwner)
+            }
+            self.oracle = oracle;
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn set_consumer(&mut self, consumer: AccountId, allowed: bool) -> Result<(), Error> {
+            if self.env().caller() != self.owner {
+                return Err(Error::CallerIsNotOwner)
+            }
+            self.consumers.insert(consumer, allowed);
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn is_consumer(&self, account: AccountId) -> bool {
+            self.consumers.get(&account).copied().unwrap_or(false)
+        }
+
+        #[ink(message)]
+        pub fn fulfill(&mut self, seed: [u8; 32]) -> Result<(), Error> {
+            if self.env().caller() != self.oracle {
+                return Err(Error::CallerIsNotOracle)
+            }
+            self.seed = seed;
+            self.round += 1;
+            self.nonce = 0;
+
+            self.env().emit_event(SeedFulfilled {
+                round: self.round,
+            });
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn oracle(&self) -> AccountId {
+            self.oracle
+        }
+
+        #[ink(message)]
+        pub fn round(&self) -> u64 {
+            self.round
+        }
+
+        #[ink(message)]
+        pub fn random_number(&self) -> u32 {
+            self.random_number
+        }
+    }
+}
@@ -5,23 +5,316 @@ use ink_lang as ink;
 
 #[ink::contract]
 mod random_number {
+    use randomness_provider::{CommitRevealProvider, RandomnessProvider};
     #[cfg(not(feature = "ink-as-dependency"))]
-    use scale::{Encode, Decode}; 
+    use scale::{Encode, Decode};
+    #[cfg(not(feature = "ink-as-dependency"))]
+    use ink_env::hash::Blake2x256;
+
+    use ink_prelude::vec::Vec;
+    use ink_storage::{
+        collections::HashMap as StorageHashMap,
+        collections::hashmap::Entry,
+        traits::{SpreadLayout, PackedLayout},
+    };
+
+    #[cfg(feature = "std")]
+    use ink_storage::traits::StorageLayout;
+
+    #[derive(Debug, PartialEq, Eq, scale::Encode, scale::Decode)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    pub enum Error {
+        CallerIsNotOwner,
+        RoundNotFound,
+        NotInCommitWindow,
+        NotInRevealWindow,
+        RevealNotEnded,
+        InsufficientDeposit,
+        AlreadyCommitted,
+        NotCommitted,
+        AlreadyRevealed,
+        InvalidReveal,
+        NotEnoughReveals,
+        AlreadyFinalized,
+        NothingToClaim,
+    }
+
+    /// A commit–reveal round: participants commit `blake2x256((account, secret))` until
+    /// `commit_end`, reveal their secrets until `reveal_end`, and the xor of all revealed
+    /// secrets becomes the seed. Commits which are not revealed are ignored, and their
+    /// deposits are shared by the participants who revealed.
+    #[derive(Debug, Default, Clone, PartialEq, Eq, scale::Encode, scale::Decode, SpreadLayout, PackedLayout)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo, StorageLayout))]
+    pub struct RevealRound {
+        commit_end: BlockNumber,  // last block to commit
+        reveal_end: BlockNumber,  // last block to reveal
+        deposit: Balance,  // deposit of each commit, refunded on reveal
+        commit_count: u32,
+        reveal_count: u32,
+        seed: [u8; 32],  // xor of all revealed secrets
+        forfeit_share: Balance,  // forfeited deposits per revealer, set on finalize
+        finalized: bool,
+    }
+
+    #[ink(event)]
+    pub struct RoundStarted {
+        #[ink(topic)]
+        round_id: u32,
+        commit_end: BlockNumber,
+        reveal_end: BlockNumber,
+    }
+
+    #[ink(event)]
+    pub struct RoundFinalized {
+        #[ink(topic)]
+        round_id: u32,
+        reveal_count: u32,
+    }
 
     #[derive(Default)]
     #[ink(storage)]
     pub struct RandomNumber {
         random_number: u32,
+        owner: AccountId,
+        /// If true, `random` draws from the seed of a finalized commit–reveal round, the salt
+        /// is the id of the round, only consumers can call it.
+        commit_reveal_mode: bool,
+        /// Minimum reveals for a round to be finalized.
+        min_reveals: u32,
+        /// Deposit for each commit.
+        commit_deposit: Balance,
+        round_id: u32,
+        rounds: StorageHashMap<u32, RevealRound>,
+        /// Mapping from (round_id, participant) to the commitment.
+        commits: StorageHashMap<(u32, AccountId), Hash>,
+        /// Mapping from (round_id, participant) to whether the forfeit share is claimed.
+        revealed: StorageHashMap<(u32, AccountId), bool>,
+        /// Mapping from round_id to the block its seed was first drawn in and the numbers
+        /// drawn from it, a seed serves that block's draw only.
+        draws: StorageHashMap<u32, (BlockNumber, u64)>,
+        /// Contracts allowed to draw in commit–reveal mode, e.g. DrawLots.
+        consumers: StorageHashMap<AccountId, bool>,
+    }
+
+    impl RandomnessProvider for RandomNumber {
+        #[ink(message)]
+        fn random(&mut self, _salt: u32) -> u32 {
+            self.next_hash(_salt);
+            self.random_number
+        }
+
+        #[ink(message)]
+        fn random_seed(&mut self, salt: u32) -> [u8; 32] {
+            self.next_hash(salt)
+        }
+    }
+
+    impl CommitRevealProvider for RandomNumber {
+        #[ink(message)]
+        fn commit_end(&self, round_id: u32) -> Option<BlockNumber> {
+            self.rounds.get(&round_id).map(|round| round.commit_end)
+        }
     }
 
     impl RandomNumber {
         #[ink(constructor)]
         pub fn new() -> Self {
-            Self::default()
+            let mut instance = Self::default();
+            instance.owner = Self::env().caller();
+            instance.min_reveals = 1;
+            instance
+        }
+
+        #[ink(message)]
+        pub fn random_number(&self) -> u32 {
+            self.random_number
+        }
+
+        /// Get `n` random numbers with one draw of entropy: the seed is expanded
+        /// by a hash chain, `h(i) = blake2x256(h(i - 1))`, each hash gives one number.
+        #[ink(message)]
+        pub fn random_batch(&mut self, salt: u32, n: u32) -> Vec<u32> {
+            let mut hash = self.next_hash(salt);
+            let mut numbers = Vec::with_capacity(n as usize);
+            for _ in 0..n {
+                numbers.push(<u32>::decode(&mut hash.as_ref()).expect("get random number failed"));
+                hash = self.env().hash_bytes::<Blake2x256>(&hash);
+            }
+            numbers
         }
 
         #[ink(message)]
-        pub fn random(&mut self, _salt: u32) -> u32 {
+        pub fn set_commit_reveal_mode(&mut self, enabled: bool) -> Result<(), Error> {
+            self.only_owner()?;
+            self.commit_reveal_mode = enabled;
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn set_consumer(&mut self, consumer: AccountId, allowed: bool) -> Result<(), Error> {
+            self.only_owner()?;
+            self.consumers.insert(consumer, allowed);
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn is_consumer(&self, account: AccountId) -> bool {
+            self.consumers.get(&account).copied().unwrap_or(false)
+        }
+
+        #[ink(message)]
+        pub fn set_commit_params(&mut self, min_reveals: u32, commit_deposit: Balance) -> Result<(), Error> {
+            self.only_owner()?;
+            self.min_reveals = min_reveals;
+            self.commit_deposit = commit_deposit;
+            Ok(())
+        }
+
+        /// Open a new round, commits are accepted for `commit_blocks` blocks and
+        /// reveals for the following `reveal_blocks` blocks.
+        #[ink(message)]
+        pub fn start_round(&mut self, commit_blocks: BlockNumber, reveal_blocks: BlockNumber) -> Result<u32, Error> {
+            self.only_owner()?;
+            let commit_end = self.env().block_number() + commit_blocks;
+            let reveal_end = commit_end + reveal_blocks;
+            self.round_id += 1;
+            self.rounds.insert(self.round_id, RevealRound {
+                commit_end,
+                reveal_end,
+                deposit: self.commit_deposit,
+                ..Default::default()
+            });
+
+            self.env().emit_event(RoundStarted {
+                round_id: self.round_id,
+                commit_end,
+                reveal_end,
+            });
+            Ok(self.round_id)
+        }
+
+        /// Commit `blake2x256((caller, secret))`, the round deposit must be transferred with the call.
+        #[ink(message, payable)]
+        pub fn commit(&mut self, round_id: u32, commitment: Hash) -> Result<(), Error> {
+            let caller = self.env().caller();
+            let current_block = self.env().block_number();
+            let transferred = self.env().transferred_balance();
+            let round = self.rounds.get_mut(&round_id).ok_or(Error::RoundNotFound)?;
+            if current_block > round.commit_end {
+                return Err(Error::NotInCommitWindow)
+            }
+            if transferred < round.deposit {
+                return Err(Error::InsufficientDeposit)
+            }
+            match self.commits.entry((round_id, caller)) {
+                Entry::Vacant(vacant) => { vacant.insert(commitment); },
+                Entry::Occupied(_) => return Err(Error::AlreadyCommitted),
+            }
+            round.commit_count += 1;
+            Ok(())
+        }
+
+        /// Reveal the secret of a commit, the deposit is refunded.
+        #[ink(message)]
+        pub fn reveal(&mut self, round_id: u32, secret: [u8; 32]) -> Result<(), Error> {
+            let caller = self.env().caller();
+            let current_block = self.env().block_number();
+            let commitment = *self.commits.get(&(round_id, caller)).ok_or(Error::NotCommitted)?;
+            let expected = self.env().hash_encoded::<Blake2x256, _>(&(caller, secret));
+
+            let round = self.rounds.get_mut(&round_id).ok_or(Error::RoundNotFound)?;
+            if current_block <= round.commit_end || current_block > round.reveal_end {
+                return Err(Error::NotInRevealWindow)
+            }
+            if self.revealed.contains_key(&(round_id, caller)) {
+                return Err(Error::AlreadyRevealed)
+            }
+            if Hash::from(expected) != commitment {
+                return Err(Error::InvalidReveal)
+            }
+
+            for (s, b) in round.seed.iter_mut().zip(secret.iter()) {
+                *s ^= *b;
+            }
+            round.reveal_count += 1;
+            let deposit = round.deposit;
+            self.revealed.insert((round_id, caller), false);
+
+            if deposit > 0 {
+                self.env().transfer(caller, deposit).expect("refund deposit failed");
+            }
+            Ok(())
+        }
+
+        /// Close a round after its reveal window, its seed can then be drawn by the consumer
+        /// bound to it in commit–reveal mode. Anyone can finalize, the seed doesn't depend on the block.
+        #[ink(message)]
+        pub fn finalize(&mut self, round_id: u32) -> Result<(), Error> {
+            let current_block = self.env().block_number();
+            let min_reveals = self.min_reveals;
+            let round = self.rounds.get_mut(&round_id).ok_or(Error::RoundNotFound)?;
+            if round.finalized {
+                return Err(Error::AlreadyFinalized)
+            }
+            if current_block <= round.reveal_end {
+                return Err(Error::RevealNotEnded)
+            }
+            if round.reveal_count < min_reveals.max(1) {
+                return Err(Error::NotEnoughReveals)
+            }
+            round.finalized = true;
+            let forfeited = round.deposit * (round.commit_count - round.reveal_count) as Balance;
+            round.forfeit_share = forfeited / round.reveal_count as Balance;
+
+            let reveal_count = round.reveal_count;
+
+            self.env().emit_event(RoundFinalized {
+                round_id,
+                reveal_count,
+            });
+            Ok(())
+        }
+
+        /// Revealers of a finalized round claim their share of the forfeited deposits.
+        #[ink(message)]
+        pub fn claim_forfeit_share(&mut self, round_id: u32) -> Result<Balance, Error> {
+            let caller = self.env().caller();
+            let round = self.rounds.get(&round_id).ok_or(Error::RoundNotFound)?;
+            let share = round.forfeit_share;
+            if !round.finalized || share == 0 {
+                return Err(Error::NothingToClaim)
+            }
+            match self.revealed.get_mut(&(round_id, caller)) {
+                Some(claimed) if !*claimed => *claimed = true,
+                _ => return Err(Error::NothingToClaim),
+            }
+            self.env().transfer(caller, share).expect("transfer forfeit share failed");
+            Ok(share)
+        }
+
+        #[ink(message)]
+        pub fn get_round(&self, round_id: u32) -> Option<RevealRound> {
+            self.rounds.get(&round_id).cloned()
+        }
+
+        #[ink(message)]
+        pub fn current_round_id(&self) -> u32 {
+            self.round_id
+        }
+
+        #[ink(message)]
+        pub fn commit_reveal_mode(&self) -> bool {
+            self.commit_reveal_mode
+        }
+
+        /// Draw a hash from the seed of commit–reveal round `_salt`, or from the chain randomness
+        /// mixed with the salt.
+        fn next_hash(&mut self, _salt: u32) -> [u8; 32] {
+            if self.commit_reveal_mode {
+                let _hash = self.next_seed_hash(_salt);
+                self.random_number = <BlockNumber>::decode(&mut _hash.as_ref()).expect("get random number failed");
+                return _hash
+            }
             let pre_random_number = self.random_number;
             let current_time: u64 = self.env().block_timestamp().into();
             let current_block = self.env().block_number();
@@ -32,12 +325,33 @@ mod random_number {
 
             let (hash, _) = self.env().random(&mix_seed);
             self.random_number = <BlockNumber>::decode(&mut hash.as_ref()).expect("get random number failed");
-            self.random_number
+            let mut _hash = [0u8; 32];
+            _hash.copy_from_slice(hash.as_ref());
+            _hash
         }
 
-        #[ink(message)]
-        pub fn random_number(&self) -> u32 {
-            self.random_number
+        /// The seed of a finalized round serves exactly one draw: the first consumer call spends
+        /// it, and only the calls of the same block, e.g. the tiers of one lottery draw, get the
+        /// next hashes of its chain. Later draws need another round, so nobody can move the
+        /// chain on to a number they have computed in advance.
+        fn next_seed_hash(&mut self, round_id: u32) -> [u8; 32] {
+            assert!(self.is_consumer(self.env().caller()), "caller is not a randomness consumer");
+            let round = self.rounds.get(&round_id).expect("commit-reveal round not found");
+            assert!(round.finalized, "the commit-reveal round isn't finalized");
+            let seed = self.env().hash_encoded::<Blake2x256, _>(&(round_id, round.seed));
+            let current_block = self.env().block_number();
+            let draw = self.draws.entry(round_id).or_insert((current_block, 0));
+            assert!(draw.0 == current_block, "the commit-reveal seed is spent");
+            draw.1 += 1;
+            let nonce = draw.1;
+            self.env().hash_encoded::<Blake2x256, _>(&(seed, nonce))
+        }
+
+        fn only_owner(&self) -> Result<(), Error> {
+            if self.env().caller() != self.owner {
+                return Err(Error::CallerIsNotOwner)
+            }
+            Ok(())
         }
     }
 }
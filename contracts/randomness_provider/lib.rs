@@ -0,0 +1,73 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+use ink_lang as ink;
+use ink_env::{
+    AccountId,
+    DefaultEnvironment,
+    Environment,
+    call::{build_call, utils::ReturnType, ExecutionInput, Selector},
+};
+
+/// Selector of `RandomnessProvider::random_seed`, fixed so any provider can be called by address.
+pub const RANDOM_SEED_SELECTOR: [u8; 4] = [0x52, 0x41, 0x4e, 0x53];
+
+/// Selector of `CommitRevealProvider::commit_end`.
+pub const COMMIT_END_SELECTOR: [u8; 4] = [0x52, 0x41, 0x4e, 0x43];
+
+pub type BlockNumber = <DefaultEnvironment as Environment>::BlockNumber;
+
+/// A source of random numbers for the draw contracts.
+/// Every provider contract implements it, and consumers call providers only through it,
+/// so the entropy source can be swapped without touching the draw algorithm.
+#[ink::trait_definition]
+pub trait RandomnessProvider {
+    /// Return a new random number, `salt` is mixed in if the provider accepts one.
+    #[ink(message, selector = 0x52414E44)]
+    fn random(&mut self, salt: u32) -> u32;
+
+    /// Return a new 32 bytes seed, consumers expand it locally instead of calling
+    /// the provider for every number they need.
+    #[ink(message, selector = 0x52414E53)]
+    fn random_seed(&mut self, salt: u32) -> [u8; 32];
+}
+
+/// Call `RandomnessProvider::random_seed` of the provider contract at `provider`.
+pub fn random_seed_of(provider: AccountId, salt: u32) -> [u8; 32] {
+    build_call::<DefaultEnvironment>()
+        .callee(provider)
+        .gas_limit(0)
+        .transferred_value(0)
+        .exec_input(
+            ExecutionInput::new(Selector::new(RANDOM_SEED_SELECTOR))
+                .push_arg(salt)
+        )
+        .returns::<ReturnType<[u8; 32]>>()
+        .fire()
+        .expect("call randomness provider failed")
+}
+
+/// A provider drawing from commit–reveal rounds, the salt of a draw is the id of the round.
+/// Consumers bind a draw to a round whose commits close after they stop taking part in
+/// the draw, e.g. a lottery round's sales, so no secret is revealed while they are open.
+#[ink::trait_definition]
+pub trait CommitRevealProvider {
+    /// Last block of the round's commit window, reveals open after it.
+    #[ink(message, selector = 0x52414E43)]
+    fn commit_end(&self, round_id: u32) -> Option<BlockNumber>;
+}
+
+/// Call `CommitRevealProvider::commit_end` of the provider contract at `provider`,
+/// None if the provider has no such round or isn't a commit–reveal provider.
+pub fn commit_end_of(provider: AccountId, round_id: u32) -> Option<BlockNumber> {
+    build_call::<DefaultEnvironment>()
+        .callee(provider)
+        .gas_limit(0)
+        .transferred_value(0)
+        .exec_input(
+            ExecutionInput::new(Selector::new(COMMIT_END_SELECTOR))
+                .push_arg(round_id)
+        )
+        .returns::<ReturnType<Option<BlockNumber>>>()
+        .fire()
+        .unwrap_or(None)
+}
@@ -0,0 +1,83 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+pub use self::seeded_random::SeededRandom;
+use ink_lang as ink;
+
+/// A deterministic randomness provider, the n-th number only depends on the seed,
+/// n and the salt. Use it for tests, and to replay a draw from a recorded seed.
+#[ink::contract]
+mod seeded_random {
+    use randomness_provider::RandomnessProvider;
+    #[cfg(not(feature = "ink-as-dependency"))]
+    use scale::Decode;
+    #[cfg(not(feature = "ink-as-dependency"))]
+    use ink_env::hash::Blake2x256;
+
+    #[derive(Debug, PartialEq, Eq, scale::Encode, scale::Decode)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    pub enum Error {
+        CallerIsNotOwner,
+    }
+
+    #[derive(Default)]
+    #[ink(storage)]
+    pub struct SeededRandom {
+        owner: AccountId,
+        seed: [u8; 32],
+        /// Numbers generated since the seed was set.
+        nonce: u64,
+        random_number: u32,
+    }
+
+    impl RandomnessProvider for SeededRandom {
+        #[ink(message)]
+        fn random(&mut self, salt: u32) -> u32 {
+            self.random_seed(salt);
+            self.random_number
+        }
+
+        #[ink(message)]
+        fn random_seed(&mut self, salt: u32) -> [u8; 32] {
+            self.nonce += 1;
+            let hash = self.env().hash_encoded::<Blake2x256, _>(&(self.seed, self.nonce, salt));
+            self.random_number = <u32>::decode(&mut hash.as_ref()).expect("get random number failed");
+            hash
+        }
+    }
+
+    impl SeededRandom {
+        #[ink(constructor)]
+        pub fn new(seed: [u8; 32]) -> Self {
+            let mut instance = Self::default();
+            instance.owner = Self::env().caller();
+            instance.seed = seed;
+            instance
+        }
+
+        /// Set a new seed and start the sequence over.
+        #[ink(message)]
+        pub fn reset(&mut self, seed: [u8; 32]) -> Result<(), Error> {
+            if self.env().caller() != self.owner {
+                return Err(Error::CallerIsNotOwner)
+            }
+            self.seed = seed;
+            self.nonce = 0;
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn seed(&self) -> [u8; 32] {
+            self.seed
+        }
+
+        #[ink(message)]
+        pub fn nonce(&self) -> u64 {
+            self.nonce
+        }
+
+        #[ink(message)]
+        pub fn random_number(&self) -> u32 {
+            self.random_number
+        }
+    }
+}
@@ -6,39 +6,214 @@ pub use self::user_tokens::UserTokens;
 mod user_tokens {
     use brush::modifiers;
     use ownable::traits::*;
+    use user_tokens_hook::UserTokensHook;
+    use nft_factory::NftFactory;
+    use psp1155::traits::PSP1155AsDependency;
 
+    use ink_env::call::FromAccountId;
     use ink_storage::{
+        lazy::Lazy,
         collections::HashMap as StorageHashMap,
-        collections::hashmap::Entry,
+        traits::{SpreadLayout, PackedLayout},
     };
-    use ink_prelude::collections::{
-        BTreeMap, 
-        btree_map::Entry as BEntry
+    use ink_prelude::{
+        vec::Vec,
+        string::String,
+        collections::BTreeMap,
     };
+    use scale::{Encode, Decode};
 
-    pub type Id = [u8; 32];
+    #[cfg(feature = "std")]
+    use ink_storage::traits::StorageLayout;
+
+    use core::ops::Bound::{Excluded, Unbounded};
+
+    pub use user_tokens_hook::Id;
+
+    /// Maximum ids returned by one `tokens_of` call.
+    pub const MAX_PAGE_SIZE: u32 = 100;
+
+    /// Fragments of each work `inventory_of` returns, `work_inventory_of` pages through the rest.
+    pub const MAX_INVENTORY_FRAGMENTS: u32 = 10;
+
+    #[derive(Debug, PartialEq, Eq, Encode, Decode)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    pub enum Error {
+        Custom(String),
+        /// The `start_after` token isn't held by the user anymore, restart from the first page.
+        CursorNotFound,
+    }
+
+    impl From<OwnableError> for Error {
+        fn from(err: OwnableError) -> Self {
+            match err {
+                OwnableError::CallerIsNotOwner => Error::Custom(String::from("O::CallerIsNotOwner")),
+                OwnableError::NewOwnerIsZero => Error::Custom(String::from("O::NewOwnerIsZero")),
+            }
+        }
+    }
+
+    /// A token a user holds, linked to the user's previous and next tokens.
+    #[derive(Debug, Default, Clone, PartialEq, Eq, Encode, Decode, SpreadLayout, PackedLayout)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo, StorageLayout))]
+    pub struct TokenEntry {
+        balance: Balance,
+        prev: Option<Id>,
+        next: Option<Id>,
+    }
+
+    /// Ends of a user's token list, tokens are kept in the order they were received.
+    #[derive(Debug, Default, Clone, PartialEq, Eq, Encode, Decode, SpreadLayout, PackedLayout)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo, StorageLayout))]
+    pub struct TokenList {
+        first: Option<Id>,
+        last: Option<Id>,
+        count: u32,
+    }
+
+    /// A user's progress on collecting the fragments of one work, with one page of the
+    /// fragments held. `missing` and `mergeable` always cover every fragment of the work.
+    #[derive(Debug, Default, Clone, PartialEq, Eq, Encode, Decode)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    pub struct WorkInventory {
+        pub origin_id: Id,
+        pub num_fragments: u128,
+        /// Fragments of the page held with their balances, in index order.
+        pub fragments: Vec<(Id, Balance)>,
+        /// Fragments of the work of which the user holds none.
+        pub missing: u128,
+        /// Full copies the fragments allow `NftFactory::merge` to make, the lowest balance among them.
+        pub mergeable: Balance,
+        /// Full copies (origin tokens) the user already holds.
+        pub full_copies: Balance,
+        /// Index the next page of fragments starts at, None after the last fragment.
+        pub next_index: Option<u128>,
+    }
 
     #[ink(storage)]
     #[derive(Default, OwnableStorage)]
     pub struct UserTokens {
         #[OwnableStorageField]
         ownable: OwnableData,
-        /// Mapping from AccountId to Map(Mapping token id to index);
+        /// Legacy layout, mapping from AccountId to Map(token id to true). It only kept which
+        /// tokens a user holds, a legacy token counts as a balance of 1 until `reconcile`.
+        /// `migrate_users` moves the tokens to `entries` and `lists` in chunks, a token also moves
+        /// on its next change. A token is in one layout only.
         user_token_ids: StorageHashMap<AccountId, BTreeMap<Id, bool>>,
-        internal_callers: StorageHashMap<AccountId, bool>
+        internal_callers: StorageHashMap<AccountId, bool>,
+        /// nft factory contract, the source of truth for `reconcile`.
+        nft: Lazy<NftFactory>,
+        nft_factory: AccountId,
+        /// Mapping from (user, token id) to the entry, tokens at a zero balance are removed.
+        entries: StorageHashMap<(AccountId, Id), TokenEntry>,
+        /// Mapping from user to the ends of the user's token list.
+        lists: StorageHashMap<AccountId, TokenList>,
     }
 
     impl Ownable for UserTokens {}
 
+    /// Called by NftFactory whenever a balance changes, set it as an internal caller.
+    /// Only the changed token is moved out of the legacy layout.
+    impl UserTokensHook for UserTokens {
+        #[ink(message)]
+        fn add_user_tokens(&mut self, user: AccountId, token_id: Id, amount: Balance) {
+            assert!(self.is_internal_caller(), "not internal caller");
+            let balance = self.balance_of(user, token_id);
+            self.take_legacy(user, token_id);
+            self.set_balance(user, token_id, balance + amount);
+        }
+
+        #[ink(message)]
+        fn delete_user_token(&mut self, user: AccountId, token_id: Id, amount: Balance) {
+            assert!(self.is_internal_caller(), "not internal caller");
+            // a missing token means drift, `reconcile` repairs it.
+            let balance = self.balance_of(user, token_id);
+            self.take_legacy(user, token_id);
+            self.set_balance(user, token_id, balance.saturating_sub(amount));
+        }
+
+        #[ink(message)]
+        fn update_user_tokens(&mut self, user: AccountId, ids_amounts: Vec<(Id, Balance)>, added: bool) {
+            for (token_id, amount) in ids_amounts {
+                if added {
+                    self.add_user_tokens(user, token_id, amount);
+                } else {
+                    self.delete_user_token(user, token_id, amount);
+                }
+            }
+        }
+    }
+
     impl UserTokens {
         #[ink(constructor)]
-        pub fn new() -> Self {
+        pub fn new(nft_factory: AccountId) -> Self {
             let mut instance = Self::default();
             let caller = Self::env().caller();
             instance._init_with_owner(caller);
+            let nft: NftFactory = FromAccountId::from_account_id(nft_factory);
+            instance.nft = Lazy::new(nft);
+            instance.nft_factory = nft_factory;
+            instance.internal_callers.insert(nft_factory, true);
             instance
         }
 
+        #[ink(message)]
+        #[modifiers(only_owner)]
+        pub fn set_nft_factory(&mut self, nft_factory: AccountId) -> Result<(), OwnableError> {
+            let nft: NftFactory = FromAccountId::from_account_id(nft_factory);
+            self.nft = Lazy::new(nft);
+            self.nft_factory = nft_factory;
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn nft_factory(&self) -> AccountId {
+            self.nft_factory
+        }
+
+        /// Repair drift: set the balances of `token_ids` for `user` to NftFactory's balances.
+        /// Returns the (id, balance) entries which were corrected.
+        #[ink(message)]
+        #[modifiers(only_owner)]
+        pub fn reconcile(&mut self, user: AccountId, token_ids: Vec<Id>) -> Result<Vec<(Id, Balance)>, OwnableError> {
+            let mut corrected = Vec::new();
+            for token_id in token_ids {
+                let balance = self.nft.balance_of(user, token_id);
+                let current = self.balance_of(user, token_id);
+                if self.take_legacy(user, token_id) || current != balance {
+                    self.set_balance(user, token_id, balance);
+                }
+                if current != balance {
+                    corrected.push((token_id, balance));
+                }
+            }
+            Ok(corrected)
+        }
+
+        /// Move up to `max_tokens` legacy tokens of the users, in the order of `users`, and
+        /// returns how many were moved. Call it again until every user `is_migrated`.
+        #[ink(message)]
+        #[modifiers(only_owner)]
+        pub fn migrate_users(&mut self, users: Vec<AccountId>, max_tokens: u32) -> Result<u32, OwnableError> {
+            let mut moved = 0;
+            for user in users {
+                if moved == max_tokens { break }
+                moved += self.migrate_user(user, max_tokens - moved);
+            }
+            Ok(moved)
+        }
+
+        #[ink(message)]
+        pub fn is_migrated(&self, user: AccountId) -> bool {
+            !self.user_token_ids.contains_key(&user)
+        }
+
+        /// Number of the user's tokens still in the legacy layout.
+        #[ink(message)]
+        pub fn legacy_token_count_of(&self, user: AccountId) -> u32 {
+            self.user_token_ids.get(&user).map_or(0, |ids| ids.len() as u32)
+        }
+
         #[ink(message)]
         #[modifiers(only_owner)]
         pub fn set_internal_caller(&mut self, user: AccountId, is: bool) -> Result<(), OwnableError> {
@@ -52,38 +227,377 @@ mod user_tokens {
             self.internal_callers.get(&caller).copied().unwrap_or(false)
         }
 
+        /// Tokens `user` holds with their balances in the order they were received, at most
+        /// `limit` (capped at `MAX_PAGE_SIZE`) of them after `start_after`. Pass the last id of
+        /// a page to get the next, the cursor fails with `CursorNotFound` once that token is gone.
+        /// A token removed and received again moves to the end. Tokens still in the legacy layout
+        /// come after the others in ascending id order.
         #[ink(message)]
-        pub fn add_user_tokens(&mut self, user: AccountId, token_id: Id) {
-            assert!(self.is_internal_caller(), "not internal caller");
-            match self.user_token_ids.entry(user) {
-                Entry::Vacant(vacant) => {
-                    let mut _bmap = BTreeMap::new();
-                    _bmap.insert(token_id, true);
-                    vacant.insert(_bmap);
-                },
-                Entry::Occupied(mut occupied) => {
-                    let mut _bmap = occupied.get_mut();
-                    match _bmap.entry(token_id) {
-                        BEntry::Vacant(vacant) => { vacant.insert(true); },
-                        _ => (), // TODO, already exist, do nothing.
-                    }
+        pub fn tokens_of(
+            &self,
+            user: AccountId,
+            start_after: Option<Id>,
+            limit: u32,
+        ) -> Result<Vec<(Id, Balance)>, Error> {
+            let limit = limit.min(MAX_PAGE_SIZE) as usize;
+            let legacy = self.user_token_ids.get(&user);
+            let (mut cursor, legacy_start) = match start_after {
+                None => (self.lists.get(&user).and_then(|list| list.first), Unbounded),
+                Some(start) => match self.entries.get(&(user, start)) {
+                    Some(entry) => (entry.next, Unbounded),
+                    None if legacy.map_or(false, |ids| ids.contains_key(&start)) => (None, Excluded(start)),
+                    None => return Err(Error::CursorNotFound),
                 },
+            };
+
+            let mut tokens = Vec::new();
+            while let Some(token_id) = cursor {
+                if tokens.len() == limit { return Ok(tokens) }
+                let entry = self.entries.get(&(user, token_id)).expect("linked token exists");
+                tokens.push((token_id, entry.balance));
+                cursor = entry.next;
             }
+            if let Some(ids) = legacy {
+                let left = limit - tokens.len();
+                tokens.extend(ids.range((legacy_start, Unbounded)).take(left).map(|(id, _)| (*id, 1)));
+            }
+            Ok(tokens)
         }
 
         #[ink(message)]
-        pub fn delete_user_token(&mut self, user: AccountId, token_id: Id) {
-            assert!(self.is_internal_caller(), "not internal caller");
-            match self.user_token_ids.entry(user) {
-                Entry::Vacant(_) => (),  // TODO: if it is empty, do nothing.
-                Entry::Occupied(mut occupied) => {
-                    let mut _bmap = occupied.get_mut();
-                    match _bmap.entry(token_id) {
-                        BEntry::Vacant(_) => (),  // TODO: if not has this token, do nothing.
-                        BEntry::Occupied(boccupied) => { boccupied.remove_entry();},
-                    }
-                },
+        pub fn token_count_of(&self, user: AccountId) -> u32 {
+            self.lists.get(&user).map_or(0, |list| list.count) + self.legacy_token_count_of(user)
+        }
+
+        #[ink(message)]
+        pub fn has_token(&self, user: AccountId, token_id: Id) -> bool {
+            self.balance_of(user, token_id) > 0
+        }
+
+        #[ink(message)]
+        pub fn balance_of(&self, user: AccountId, token_id: Id) -> Balance {
+            if let Some(entry) = self.entries.get(&(user, token_id)) {
+                return entry.balance
+            }
+            match self.user_token_ids.get(&user) {
+                Some(ids) if ids.contains_key(&token_id) => 1,
+                _ => 0,
+            }
+        }
+
+        /// The user's progress on the fragments of the work of `origin_id`, with the fragments
+        /// held from index `start_index` (counted from 1), at most `limit` (capped at
+        /// `MAX_PAGE_SIZE`) of them. Counting `missing` and `mergeable` reads every fragment,
+        /// as `NftFactory::merge` does. None if NftFactory has no such work or the work wasn't
+        /// split into fragments.
+        #[ink(message)]
+        pub fn work_inventory_of(
+            &self,
+            user: AccountId,
+            origin_id: Id,
+            start_index: u128,
+            limit: u32,
+        ) -> Option<WorkInventory> {
+            let num_fragments = self.nft.num_fragments_of(origin_id);
+            if num_fragments == 0 { return None }
+            let start = start_index.max(1);
+            let end = num_fragments.min(start.saturating_add(limit.min(MAX_PAGE_SIZE) as u128) - 1);
+
+            let mut inventory = WorkInventory {
+                origin_id,
+                num_fragments,
+                mergeable: Balance::MAX,
+                full_copies: self.balance_of(user, origin_id),
+                ..Default::default()
+            };
+            for index in 1..=num_fragments {
+                let token_id = fragment_id(origin_id, index);
+                let balance = self.balance_of(user, token_id);
+                inventory.mergeable = inventory.mergeable.min(balance);
+                if balance == 0 {
+                    inventory.missing += 1;
+                } else if start <= index && index <= end {
+                    inventory.fragments.push((token_id, balance));
+                }
+            }
+            inventory.next_index = if end < num_fragments { Some(end + 1) } else { None };
+            Some(inventory)
+        }
+
+        /// Works of the fragments among `limit` (capped at `MAX_PAGE_SIZE`) of the user's tokens
+        /// after `start_after`, as in `tokens_of`, with the `start_after` of the next page, None
+        /// after the last one. A work with fragments on several pages is returned on each of them,
+        /// with its first `MAX_INVENTORY_FRAGMENTS` fragments held and its counts over all of them.
+        #[ink(message)]
+        pub fn inventory_of(
+            &self,
+            user: AccountId,
+            start_after: Option<Id>,
+            limit: u32,
+        ) -> Result<(Vec<WorkInventory>, Option<Id>), Error> {
+            let tokens = self.tokens_of(user, start_after, limit)?;
+            let next = if tokens.len() == limit.min(MAX_PAGE_SIZE) as usize {
+                tokens.last().map(|(token_id, _)| *token_id)
+            } else {
+                None
+            };
+
+            let mut origin_ids = Vec::new();
+            for (token_id, _) in tokens {
+                if !is_fragment(token_id) { continue }
+                let origin_id = origin_of(token_id);
+                if !origin_ids.contains(&origin_id) {
+                    origin_ids.push(origin_id);
+                }
+            }
+            let works = origin_ids.into_iter()
+                .filter_map(|origin_id| self.work_inventory_of(user, origin_id, 1, MAX_INVENTORY_FRAGMENTS))
+                .collect();
+            Ok((works, next))
+        }
+    }
+
+    // NftFactory ids: the first 16 bytes are the origin id, the second 16 bytes count the
+    // fragments as `nft_factory::increase_id` does: byte 31 counts up to 0xFF and stays there,
+    // then byte 30 counts, and so on down to byte 17. Byte 16 is never used, and from index
+    // 15 * 255 on every fragment gets the same id.
+    fn origin_of(token_id: Id) -> Id {
+        let mut origin_id = token_id;
+        origin_id[16..].copy_from_slice(&[0; 16]);
+        origin_id
+    }
+
+    fn is_fragment(token_id: Id) -> bool {
+        token_id[16..] != [0; 16]
+    }
+
+    fn fragment_id(origin_id: Id, index: u128) -> Id {
+        let index = index.min(15 * 255);
+        let (full, rem) = ((index / 255) as usize, (index % 255) as u8);
+        let mut token_id = origin_id;
+        for byte in &mut token_id[32 - full..] {
+            *byte = u8::MAX;
+        }
+        if full < 15 {
+            token_id[31 - full] = rem;
+        }
+        token_id
+    }
+
+    // private functions
+    impl UserTokens {
+        /// Move up to `max_tokens` of the user's legacy tokens, lowest ids first, to the end of
+        /// the list at a balance of 1, `reconcile` sets their real balances. What is left stays
+        /// in the legacy map for the next call. Returns how many were moved.
+        fn migrate_user(&mut self, user: AccountId, max_tokens: u32) -> u32 {
+            let mut legacy = match self.user_token_ids.take(&user) {
+                Some(legacy) => legacy,
+                None => return 0,
+            };
+            let mut moved = 0;
+            while moved < max_tokens {
+                let token_id = match legacy.keys().next() {
+                    Some(token_id) => *token_id,
+                    None => break,
+                };
+                legacy.remove(&token_id);
+                self.set_balance(user, token_id, 1);
+                moved += 1;
+            }
+            if !legacy.is_empty() {
+                self.user_token_ids.insert(user, legacy);
+            }
+            moved
+        }
+
+        /// Remove a token from the user's legacy map, returns false if it wasn't there.
+        fn take_legacy(&mut self, user: AccountId, token_id: Id) -> bool {
+            if !self.user_token_ids.get(&user).map_or(false, |ids| ids.contains_key(&token_id)) {
+                return false
+            }
+            let ids = self.user_token_ids.get_mut(&user).expect("user has legacy tokens");
+            ids.remove(&token_id);
+            if ids.is_empty() {
+                self.user_token_ids.take(&user);
+            }
+            true
+        }
+
+        /// Set the balance of a token, a new token is appended to the user's list
+        /// and a token at a zero balance is unlinked.
+        fn set_balance(&mut self, user: AccountId, token_id: Id, balance: Balance) {
+            if balance == 0 {
+                self.unlink(user, token_id);
+                return
+            }
+            if let Some(entry) = self.entries.get_mut(&(user, token_id)) {
+                entry.balance = balance;
+                return
+            }
+
+            let list = self.lists.entry(user).or_insert_with(TokenList::default);
+            let prev = list.last;
+            list.last = Some(token_id);
+            if list.first.is_none() {
+                list.first = Some(token_id);
+            }
+            list.count += 1;
+            if let Some(prev_id) = prev {
+                self.entries.get_mut(&(user, prev_id)).expect("linked token exists").next = Some(token_id);
+            }
+            self.entries.insert((user, token_id), TokenEntry { balance, prev, next: None });
+        }
+
+        fn unlink(&mut self, user: AccountId, token_id: Id) {
+            let entry = match self.entries.take(&(user, token_id)) {
+                Some(entry) => entry,
+                None => return,
+            };
+            if let Some(prev_id) = entry.prev {
+                self.entries.get_mut(&(user, prev_id)).expect("linked token exists").next = entry.next;
+            }
+            if let Some(next_id) = entry.next {
+                self.entries.get_mut(&(user, next_id)).expect("linked token exists").prev = entry.prev;
+            }
+
+            let list = self.lists.get_mut(&user).expect("user has a list");
+            if entry.prev.is_none() {
+                list.first = entry.next;
+            }
+            if entry.next.is_none() {
+                list.last = entry.prev;
+            }
+            list.count -= 1;
+            if list.count == 0 {
+                self.lists.take(&user);
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use ink_lang as ink;
+        use nft_factory::increase_id;
+
+        fn id(n: u8) -> Id {
+            let mut token_id = [0; 32];
+            token_id[15] = n;
+            token_id
+        }
+
+        fn ids_of(tokens: &UserTokens, user: AccountId) -> Vec<Id> {
+            tokens.tokens_of(user, None, MAX_PAGE_SIZE)
+                .expect("first page")
+                .into_iter()
+                .map(|(token_id, _)| token_id)
+                .collect()
+        }
+
+        #[ink::test]
+        fn unlinks_head_middle_and_tail() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>()
+                .expect("off-chain accounts");
+            // alice deploys, so she is the owner and stands in for NftFactory.
+            let mut tokens = UserTokens::new(accounts.alice);
+            let user = accounts.bob;
+            for n in 1..=5 {
+                tokens.add_user_tokens(user, id(n), n as Balance);
+            }
+
+            tokens.delete_user_token(user, id(1), 1);
+            assert_eq!(ids_of(&tokens, user), vec![id(2), id(3), id(4), id(5)]);
+            tokens.delete_user_token(user, id(3), 3);
+            assert_eq!(ids_of(&tokens, user), vec![id(2), id(4), id(5)]);
+            tokens.delete_user_token(user, id(5), 5);
+            assert_eq!(ids_of(&tokens, user), vec![id(2), id(4)]);
+            assert_eq!(tokens.token_count_of(user), 2);
+            assert_eq!(tokens.entries.get(&(user, id(2))).and_then(|entry| entry.prev), None);
+            assert_eq!(tokens.entries.get(&(user, id(4))).and_then(|entry| entry.next), None);
+
+            // a partial delete keeps the token in place, a token received again moves to the end.
+            tokens.delete_user_token(user, id(2), 1);
+            tokens.add_user_tokens(user, id(3), 1);
+            assert_eq!(tokens.tokens_of(user, None, MAX_PAGE_SIZE), Ok(vec![(id(2), 1), (id(4), 4), (id(3), 1)]));
+
+            for n in 2..=4 {
+                tokens.delete_user_token(user, id(n), n as Balance);
             }
+            assert!(ids_of(&tokens, user).is_empty());
+            assert!(!tokens.lists.contains_key(&user));
+        }
+
+        #[ink::test]
+        fn migrates_across_chunks() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>()
+                .expect("off-chain accounts");
+            let mut tokens = UserTokens::new(accounts.alice);
+            let (bob, charlie) = (accounts.bob, accounts.charlie);
+            tokens.user_token_ids.insert(bob, (1..=5).map(|n| (id(n), true)).collect());
+            tokens.user_token_ids.insert(charlie, (6..=7).map(|n| (id(n), true)).collect());
+            let all_ids: Vec<Id> = (1..=5).map(id).collect();
+
+            assert_eq!(tokens.migrate_users(vec![bob, charlie], 3), Ok(3));
+            assert!(!tokens.is_migrated(bob));
+            assert_eq!(tokens.legacy_token_count_of(bob), 2);
+            assert_eq!(tokens.token_count_of(bob), 5);
+            // the moved tokens come first, the legacy ones after them.
+            assert_eq!(ids_of(&tokens, bob), all_ids);
+            assert_eq!(tokens.tokens_of(bob, Some(id(3)), 1), Ok(vec![(id(4), 1)]));
+
+            // the rest of bob's tokens, then charlie's.
+            assert_eq!(tokens.migrate_users(vec![bob, charlie], 3), Ok(3));
+            assert!(tokens.is_migrated(bob));
+            assert_eq!(tokens.legacy_token_count_of(charlie), 1);
+            assert_eq!(tokens.migrate_users(vec![bob, charlie], 3), Ok(1));
+            assert_eq!(tokens.migrate_users(vec![bob, charlie], 3), Ok(0));
+
+            assert!(tokens.is_migrated(charlie));
+            assert_eq!(ids_of(&tokens, bob), all_ids);
+            assert_eq!(ids_of(&tokens, charlie), vec![id(6), id(7)]);
+            assert_eq!(tokens.balance_of(bob, id(5)), 1);
+            assert_eq!(tokens.token_count_of(bob), 5);
+        }
+
+        #[ink::test]
+        fn rejects_stale_cursor() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>()
+                .expect("off-chain accounts");
+            let mut tokens = UserTokens::new(accounts.alice);
+            let user = accounts.bob;
+            tokens.user_token_ids.insert(user, [(id(9), true)].iter().copied().collect());
+            for n in 1..=3 {
+                tokens.add_user_tokens(user, id(n), 1);
+            }
+
+            assert_eq!(tokens.tokens_of(user, None, 2), Ok(vec![(id(1), 1), (id(2), 1)]));
+            assert_eq!(tokens.tokens_of(user, Some(id(2)), 2), Ok(vec![(id(3), 1), (id(9), 1)]));
+            tokens.delete_user_token(user, id(2), 1);
+            assert_eq!(tokens.tokens_of(user, Some(id(2)), 2), Err(Error::CursorNotFound));
+            assert_eq!(tokens.tokens_of(user, Some(id(4)), 2), Err(Error::CursorNotFound));
+
+            // a migrated token keeps its place after the others, its id stays a valid cursor.
+            assert_eq!(tokens.migrate_users(vec![user], 1), Ok(1));
+            assert_eq!(tokens.tokens_of(user, Some(id(3)), 2), Ok(vec![(id(9), 1)]));
+            assert_eq!(tokens.tokens_of(user, Some(id(9)), 2), Ok(vec![]));
+        }
+
+        #[ink::test]
+        fn fragment_ids_follow_increase_id() {
+            let origin_id = id(7);
+            let mut token_id = origin_id;
+            // past the carry into byte 30 and the last index with a distinct id.
+            for index in 1..=15 * 255 + 2 {
+                token_id = increase_id(token_id, 32);
+                assert_eq!(fragment_id(origin_id, index), token_id, "fragment {}", index);
+            }
+
+            let mut carried = origin_id;
+            carried[30] = 1;
+            carried[31] = u8::MAX;
+            assert_eq!(fragment_id(origin_id, 256), carried);
+            assert_eq!(origin_of(carried), origin_id);
+            assert!(is_fragment(carried) && !is_fragment(origin_id));
         }
     }
 }
@@ -0,0 +1,90 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+use ink_lang as ink;
+use ink_prelude::vec::Vec;
+use ink_env::{
+    AccountId,
+    DefaultEnvironment,
+    Environment,
+    call::{build_call, utils::ReturnType, ExecutionInput, Selector},
+};
+
+pub type Id = [u8; 32];
+pub type Balance = <DefaultEnvironment as Environment>::Balance;
+
+/// Selectors of `UserTokensHook` messages, fixed so the index can be called by address.
+pub const ADD_USER_TOKENS_SELECTOR: [u8; 4] = [0x55, 0x54, 0x4b, 0x41];
+pub const DELETE_USER_TOKEN_SELECTOR: [u8; 4] = [0x55, 0x54, 0x4b, 0x44];
+pub const UPDATE_USER_TOKENS_SELECTOR: [u8; 4] = [0x55, 0x54, 0x4b, 0x55];
+
+/// The per-user token index NftFactory keeps up to date.
+/// UserTokens implements it and NftFactory calls it only by address, so neither crate
+/// depends on the other.
+#[ink::trait_definition]
+pub trait UserTokensHook {
+    /// `user` received `amount` of `token_id`.
+    #[ink(message, selector = 0x55544B41)]
+    fn add_user_tokens(&mut self, user: AccountId, token_id: Id, amount: Balance);
+
+    /// `user` sent or burned `amount` of `token_id`, the token is removed at a zero balance.
+    #[ink(message, selector = 0x55544B44)]
+    fn delete_user_token(&mut self, user: AccountId, token_id: Id, amount: Balance);
+
+    /// `user` received (`added`) or lost the amounts of the tokens, one call for a batch.
+    #[ink(message, selector = 0x55544B55)]
+    fn update_user_tokens(&mut self, user: AccountId, ids_amounts: Vec<(Id, Balance)>, added: bool);
+}
+
+/// Call `UserTokensHook::add_user_tokens` of the index contract at `hook`.
+/// The error is returned rather than trapping, NftFactory only reports it.
+pub fn add_user_tokens_of(hook: AccountId, user: AccountId, token_id: Id, amount: Balance) -> ink_env::Result<()> {
+    build_call::<DefaultEnvironment>()
+        .callee(hook)
+        .gas_limit(0)
+        .transferred_value(0)
+        .exec_input(
+            ExecutionInput::new(Selector::new(ADD_USER_TOKENS_SELECTOR))
+                .push_arg(user)
+                .push_arg(token_id)
+                .push_arg(amount)
+        )
+        .returns::<ReturnType<()>>()
+        .fire()
+}
+
+/// Call `UserTokensHook::delete_user_token` of the index contract at `hook`.
+pub fn delete_user_token_of(hook: AccountId, user: AccountId, token_id: Id, amount: Balance) -> ink_env::Result<()> {
+    build_call::<DefaultEnvironment>()
+        .callee(hook)
+        .gas_limit(0)
+        .transferred_value(0)
+        .exec_input(
+            ExecutionInput::new(Selector::new(DELETE_USER_TOKEN_SELECTOR))
+                .push_arg(user)
+                .push_arg(token_id)
+                .push_arg(amount)
+        )
+        .returns::<ReturnType<()>>()
+        .fire()
+}
+
+/// Call `UserTokensHook::update_user_tokens` of the index contract at `hook`.
+pub fn update_user_tokens_of(
+    hook: AccountId,
+    user: AccountId,
+    ids_amounts: Vec<(Id, Balance)>,
+    added: bool,
+) -> ink_env::Result<()> {
+    build_call::<DefaultEnvironment>()
+        .callee(hook)
+        .gas_limit(0)
+        .transferred_value(0)
+        .exec_input(
+            ExecutionInput::new(Selector::new(UPDATE_USER_TOKENS_SELECTOR))
+                .push_arg(user)
+                .push_arg(ids_amounts)
+                .push_arg(added)
+        )
+        .returns::<ReturnType<()>>()
+        .fire()
+}